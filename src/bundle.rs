@@ -0,0 +1,357 @@
+use crate::common::{
+    capture_session_name_from_script, get_session_files_dir, get_session_script_path_for,
+    UserOption, TMUX_SESSION_RE,
+};
+use crate::editor_session::relocate_editor_sessions;
+use crate::list_sessions::{find_saved_sessions, get_saved_sessions, get_session_label};
+use crate::move_session::{relocate_path, relocate_session_script};
+use crate::session_file::{verify_checksum, write_session_script};
+use crate::ui::get_user_option;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path};
+
+const MANIFEST_FILE: &str = "manifest.toml";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// Home directory of the user that exported the bundle
+    home: String,
+    sessions: Vec<ManifestSession>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestSession {
+    /// Path of the session script inside the bundle
+    file: String,
+    name: String,
+    directory: String,
+    profile: Option<String>,
+    /// Directory of the files saved along with the script on the exporting
+    /// machine, bundled next to the script with the same name
+    #[serde(default)]
+    files_dir: Option<String>,
+}
+
+pub fn export_bundle(session: Option<String>, all: bool, output: &Path) {
+    let sessions = match session {
+        Some(query) if !all => find_saved_sessions(&query),
+        _ => get_saved_sessions(),
+    };
+    if sessions.is_empty() {
+        eprintln!("No saved sessions to export");
+        std::process::exit(1);
+    }
+
+    let mut manifest = Manifest {
+        version: 1,
+        home: env::var("HOME").unwrap_or_default(),
+        sessions: vec![],
+    };
+
+    let file = File::create(output).unwrap_or_else(|err| {
+        eprintln!("Error: Could not create {}: {}", output.display(), err);
+        std::process::exit(1);
+    });
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    for session in &sessions {
+        let file_name = session.path.file_name().unwrap().to_string_lossy();
+        let bundle_path = format!("sessions/{}", file_name);
+        archive
+            .append_path_with_name(&session.path, &bundle_path)
+            .expect("Could not add the session script to the bundle");
+
+        let files_dir = get_session_files_dir(&session.path);
+        let has_files = files_dir.is_dir();
+        if has_files {
+            let bundle_dir = format!(
+                "sessions/{}",
+                files_dir.file_name().unwrap().to_string_lossy()
+            );
+            archive
+                .append_dir_all(&bundle_dir, &files_dir)
+                .expect("Could not add the session files to the bundle");
+        }
+
+        manifest.sessions.push(ManifestSession {
+            file: bundle_path,
+            name: session.name.clone(),
+            directory: session.directory.clone(),
+            profile: session.profile.clone(),
+            files_dir: has_files.then(|| files_dir.to_string_lossy().to_string()),
+        });
+        println!("Exported {}", get_session_label(session));
+    }
+
+    let manifest_content = toml::to_string_pretty(&manifest).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, MANIFEST_FILE, manifest_content.as_bytes())
+        .expect("Could not add the manifest to the bundle");
+
+    archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .expect("Could not write the bundle");
+
+    println!(
+        "{} sessions exported to {}",
+        sessions.len(),
+        output.display()
+    );
+}
+
+pub fn import_bundle(bundle: &Path, remap_home: bool, force: bool) {
+    let file = File::open(bundle).unwrap_or_else(|err| {
+        eprintln!("Error: Could not open {}: {}", bundle.display(), err);
+        std::process::exit(1);
+    });
+
+    // Read the whole bundle first, the manifest comes last
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut files: Vec<(String, String)> = Vec::new();
+    let entries = archive.entries().unwrap_or_else(|err| {
+        eprintln!("Error: Could not read {}: {}", bundle.display(), err);
+        std::process::exit(1);
+    });
+    for entry in entries {
+        // A corrupt entry only loses the session it belongs to
+        let result = entry.and_then(|mut entry| {
+            let path = entry.path()?.to_string_lossy().to_string();
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", &path, err)))?;
+            Ok((path, content))
+        });
+        match result {
+            Ok(file) => files.push(file),
+            Err(err) => eprintln!("Warning: Skipping an entry of the bundle: {}", err),
+        }
+    }
+
+    let manifest: Manifest = files
+        .iter()
+        .find(|(path, _)| path == MANIFEST_FILE)
+        .and_then(|(_, content)| toml::from_str(content).ok())
+        .unwrap_or_else(|| {
+            eprintln!("Error: {} is not a tmuxession bundle", bundle.display());
+            std::process::exit(1);
+        });
+
+    let home = env::var("HOME").unwrap_or_default();
+    let mut imported = 0;
+    for session in &manifest.sessions {
+        let Some((_, shell_script)) = files.iter().find(|(path, _)| *path == session.file) else {
+            eprintln!("Warning: {} is missing from the bundle", &session.file);
+            continue;
+        };
+        // The script is stamped again once imported, so a broken one would
+        // look verified afterwards
+        if !force && !verify_checksum(shell_script) {
+            eprintln!(
+                "Error: The checksum of {} doesn't match its content, skipping `{}`.",
+                &session.file, &session.name
+            );
+            eprintln!("Import it anyway with `--force`.");
+            continue;
+        }
+
+        let mut directory = session.directory.clone();
+        let mut shell_script = shell_script.clone();
+        if remap_home && manifest.home != home {
+            if let Some(new_directory) = relocate_path(&directory, &manifest.home, &home) {
+                shell_script = relocate_session_script(
+                    &shell_script,
+                    &directory,
+                    &manifest.home,
+                    &home,
+                    |_, _| {},
+                );
+                directory = new_directory;
+            }
+        }
+
+        let Some((shell_script, profile)) =
+            resolve_collisions(shell_script, &directory, session.profile.clone())
+        else {
+            println!("Skipped `{}`: {}", &session.name, &directory);
+            continue;
+        };
+
+        let file_path = get_session_script_path_for(&directory, profile.as_deref());
+        let shell_script = match &session.files_dir {
+            Some(files_dir) => {
+                let new_files_dir = get_session_files_dir(&file_path);
+                import_session_files(&files, &session.file, &new_files_dir);
+                relocate_editor_sessions(&shell_script, Path::new(files_dir), &new_files_dir)
+            }
+            None => shell_script,
+        };
+        if let Err(err) = write_session_script(&file_path, &shell_script) {
+            eprintln!("Error: Could not write {}: {}", file_path.display(), err);
+            continue;
+        }
+        println!(
+            "Imported `{}`: {}",
+            capture_session_name_from_script(&shell_script),
+            &directory
+        );
+        imported += 1;
+    }
+
+    println!("{} sessions imported", imported);
+}
+
+/// Writes the files bundled along with the script `bundle_file` to `files_dir`.
+fn import_session_files(files: &[(String, String)], bundle_file: &str, files_dir: &Path) {
+    let bundle_dir = get_session_files_dir(Path::new(bundle_file));
+    for (path, content) in files {
+        let Ok(file_name) = Path::new(path).strip_prefix(&bundle_dir) else {
+            continue;
+        };
+        if file_name.as_os_str().is_empty() {
+            continue;
+        }
+        if !is_relative_file_name(file_name) {
+            eprintln!(
+                "Warning: Skipping {} of the bundle, it leaves {}",
+                path,
+                files_dir.display()
+            );
+            continue;
+        }
+        let file_path = files_dir.join(file_name);
+        let result = fs::create_dir_all(file_path.parent().unwrap())
+            .and_then(|_| fs::write(&file_path, content));
+        if let Err(err) = result {
+            eprintln!("Error: Could not write {}: {}", file_path.display(), err);
+        }
+    }
+}
+
+/// Whether a path of the bundle stays inside the directory it is joined to.
+fn is_relative_file_name(file_name: &Path) -> bool {
+    file_name
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Asks the user what to do when the imported session would replace a saved
+/// one or has the same name as a saved one. Returns `None` to skip it.
+pub fn resolve_collisions(
+    mut shell_script: String,
+    directory: &str,
+    mut profile: Option<String>,
+) -> Option<(String, Option<String>)> {
+    while get_session_script_path_for(directory, profile.as_deref()).exists() {
+        let title = format!(
+            "A session is already saved for {}{}.",
+            directory,
+            profile
+                .as_ref()
+                .map_or(String::new(), |profile| format!(" [{}]", profile))
+        );
+        let options = vec![
+            UserOption {
+                keybind: 'O',
+                label: "[O]verwrite the saved session".to_string(),
+                header: false,
+            },
+            UserOption {
+                keybind: 'P',
+                label: "[P]rofile, import under a different profile".to_string(),
+                header: false,
+            },
+            UserOption {
+                keybind: 's',
+                label: "[s]kip".to_string(),
+                header: false,
+            },
+        ];
+        match get_user_option(&title, options) {
+            'O' => break,
+            'P' => profile = Some(read_line("Enter profile name: ")),
+            _ => return None,
+        }
+    }
+
+    loop {
+        let session_name = capture_session_name_from_script(&shell_script);
+        let is_name_taken = get_saved_sessions()
+            .iter()
+            .any(|session| session.name == session_name && session.directory != directory);
+        if !is_name_taken {
+            break;
+        }
+
+        let title = format!(
+            "A session named `{}` is already saved for another directory.",
+            session_name
+        );
+        let options = vec![
+            UserOption {
+                keybind: 'K',
+                label: "[K]eep the name".to_string(),
+                header: false,
+            },
+            UserOption {
+                keybind: 'R',
+                label: "[R]ename the imported session".to_string(),
+                header: false,
+            },
+            UserOption {
+                keybind: 's',
+                label: "[s]kip".to_string(),
+                header: false,
+            },
+        ];
+        match get_user_option(&title, options) {
+            'K' => break,
+            'R' => {
+                let new_name = read_line("Enter new session name: ");
+                shell_script = TMUX_SESSION_RE
+                    .replace(&shell_script, |_: &regex::Captures| {
+                        format!("session_name={}", new_name)
+                    })
+                    .to_string();
+            }
+            _ => return None,
+        }
+    }
+
+    Some((shell_script, profile))
+}
+
+fn read_line(prompt: &str) -> String {
+    let mut line = String::new();
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+    io::stdin().read_line(&mut line).unwrap();
+    line.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_files_inside_the_files_dir() {
+        assert!(is_relative_file_name(Path::new("pane_0_0.vim")));
+        assert!(is_relative_file_name(Path::new("nested/pane_0_0.vim")));
+        assert!(!is_relative_file_name(Path::new("../../.bashrc")));
+        assert!(!is_relative_file_name(Path::new("nested/../../x")));
+        assert!(!is_relative_file_name(Path::new("/etc/profile")));
+        assert!(!is_relative_file_name(Path::new("./pane_0_0.vim")));
+    }
+}
@@ -1,20 +1,43 @@
+use crate::config::get_config;
+use crate::hooks::HookEvent;
 use crate::tmux_commands::get_current_pane_cwd;
+use crate::ui::get_user_option;
+use clap::ValueEnum;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::env;
-use std::path::PathBuf;
-use urlencoding::encode;
+use std::fs;
+use std::path::{Path, PathBuf};
+use urlencoding::{decode, encode};
 use xdg::BaseDirectories;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct TmuxPane {
     pub id: String,
     pub cwd: String,
     pub active: bool,
     pub commands: Vec<String>,
+    /// The host the pane is connected to when its last command is `ssh`
+    pub remote: Option<RemotePane>,
+    /// The last commands run in the pane's shell, recorded by the shell hook
+    pub history: Vec<String>,
+    /// Whether the last command is typed on restore without being run
+    pub prefill: bool,
 }
 
-#[derive(Debug)]
+/// An SSH connection running in a pane, restored in the same remote
+/// directory and with the same remote command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemotePane {
+    pub host: String,
+    pub user: Option<String>,
+    /// Options of the ssh command, like the port or the identity file
+    pub options: Vec<String>,
+    pub cwd: Option<String>,
+    pub command: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
 pub struct TmuxWindow {
     pub id: String,
     pub name: String,
@@ -24,54 +47,473 @@ pub struct TmuxWindow {
     pub panes: Vec<TmuxPane>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct TmuxSession {
     pub name: String,
+    /// The directory the session is saved for
+    pub root: String,
+    pub environment: Vec<(String, String)>,
     pub windows: Vec<TmuxWindow>,
+    /// Panes whose commands are run in order by `tmuxession restore`
+    pub startup: Vec<PaneStartup>,
+    pub hooks: Vec<SessionHook>,
+}
+
+/// Shell command run on restore or save, given by a
+/// `# @hook <event> <command>` line in the session script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionHook {
+    pub event: HookEvent,
+    pub command: String,
+}
+
+/// When the commands of a pane are run on restore, given by a
+/// `# @startup <window>.<pane> key=value...` line in the session script.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PaneStartup {
+    /// The window name and the position of the pane in it, from 0
+    pub pane: String,
+    pub depends_on: Vec<String>,
+    /// Port, or host:port, that accepts connections once the pane is ready
+    pub wait_for_port: Option<String>,
+    pub wait_for_file: Option<String>,
+    /// Regex matching the output of the pane once it is ready
+    pub wait_for_output: Option<String>,
+    /// Seconds to wait before running the commands
+    pub delay: Option<f64>,
+    /// Seconds to wait for the pane to be ready
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct SavedSession {
+    pub path: PathBuf,
+    pub name: String,
+    pub directory: String,
+    pub profile: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct UserOption {
     pub label: String,
     pub keybind: char,
+    /// Headers are shown to group the options but can't be selected
+    pub header: bool,
 }
 
 lazy_static! {
     pub static ref TMUX_SESSION_RE: Regex = Regex::new(r"(?m)^session_name=(.*)$").unwrap();
+    pub static ref TMUX_PANE_CWD_RE: Regex = Regex::new(
+        r#"(?m)^(?:\w+=\$\()?tmux (?:new-window|split-window) .*?-c ((?:"[^"]*"|'[^']*'|[^\s"'])+)"#
+    )
+    .unwrap();
 }
 
 pub fn get_data_dir() -> PathBuf {
-    let xdg_dirs = BaseDirectories::with_prefix("tmuxession").unwrap();
-    let data_dir = xdg_dirs.get_data_home();
+    let data_dir = match &get_config().data_dir {
+        Some(data_dir) => data_dir.clone(),
+        None => {
+            let xdg_dirs = BaseDirectories::with_prefix("tmuxession").unwrap();
+            xdg_dirs.get_data_home()
+        }
+    };
     std::fs::create_dir_all(&data_dir).expect("Could not create tmuxession data directory");
     data_dir
 }
 
-pub fn get_session_script_path() -> PathBuf {
-    let xdg_dirs = BaseDirectories::with_prefix("tmuxession").unwrap();
-    let current_dir = match is_inside_tmux() {
+pub fn get_current_dir() -> String {
+    match is_inside_tmux() {
         true => get_current_pane_cwd(),
         false => env::current_dir().unwrap().to_string_lossy().to_string(),
+    }
+}
+
+/// Name of the session script that can be kept inside the project itself
+pub const LOCAL_SCRIPT_NAME: &str = ".tmuxession.sh";
+
+pub fn get_session_script_path(profile: Option<&str>) -> PathBuf {
+    let current_dir = get_current_dir();
+
+    // A session script checked into the project takes precedence
+    let local_script_path = get_local_script_path_for(&current_dir, profile);
+    if local_script_path.is_file() {
+        return local_script_path;
+    }
+
+    get_session_script_path_for(&current_dir, profile)
+}
+
+/// Like `get_session_script_path`, but walks up the parent directories until
+/// a saved session is found, stopping at `$HOME` or the root of a repository.
+/// Without a profile, the user picks one if the directory has several.
+/// Falls back to the path for the current directory if nothing was found.
+pub fn find_session_script_path(profile: Option<&str>) -> PathBuf {
+    let current_dir = get_current_dir();
+    let home = env::var("HOME").unwrap_or_default();
+
+    for directory in Path::new(&current_dir).ancestors() {
+        let directory_str = directory.to_string_lossy();
+        let mut scripts = get_session_scripts_for(&directory_str);
+        if profile.is_some() {
+            scripts.retain(|(script_profile, _)| script_profile.as_deref() == profile);
+        }
+
+        if !scripts.is_empty() {
+            if directory_str != current_dir {
+                println!("Using the session saved for {}", &directory_str);
+            }
+            if scripts.len() == 1 {
+                return scripts.remove(0).1;
+            }
+            return get_profile_option(&directory_str, scripts);
+        }
+
+        let is_vcs_root = [".git", ".hg", ".jj", ".svn"]
+            .iter()
+            .any(|vcs_dir| directory.join(vcs_dir).exists());
+        if is_vcs_root || directory_str == home {
+            break;
+        }
+    }
+
+    get_session_script_path(profile)
+}
+
+fn get_profile_option(directory: &str, scripts: Vec<(Option<String>, PathBuf)>) -> PathBuf {
+    let title = format!("Multiple sessions are saved for {}:", directory);
+    let mut options: Vec<UserOption> = scripts
+        .iter()
+        .enumerate()
+        .map(|(i, (profile, _))| UserOption {
+            label: format!("[{}] {}", i + 1, profile.as_deref().unwrap_or("default")),
+            keybind: (i as u8 + b'1') as char,
+            header: false,
+        })
+        .collect();
+    options.push(UserOption {
+        label: "[q] Quit".to_string(),
+        keybind: 'q',
+        header: false,
+    });
+
+    let choice = get_user_option(&title, options);
+    if choice == 'q' {
+        std::process::exit(0);
+    }
+    let index = (choice as u8).checked_sub(b'1').unwrap();
+    scripts.into_iter().nth(index as usize).unwrap().1
+}
+
+/// All the session scripts saved for `directory`, one per profile, preferring
+/// the project-local ones.
+pub fn get_session_scripts_for(directory: &str) -> Vec<(Option<String>, PathBuf)> {
+    let mut scripts: Vec<(Option<String>, PathBuf)> = Vec::new();
+
+    for dir in [PathBuf::from(directory), get_data_dir()] {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let profile = if dir == get_data_dir() {
+                match parse_script_file_name(&file_name) {
+                    Some((script_directory, profile)) if script_directory == directory => profile,
+                    _ => continue,
+                }
+            } else {
+                match parse_local_script_file_name(&file_name) {
+                    Some(profile) => profile,
+                    None => continue,
+                }
+            };
+            if path.is_file() && !scripts.iter().any(|(p, _)| *p == profile) {
+                scripts.push((profile, path));
+            }
+        }
+    }
+
+    scripts.sort_by(|a, b| a.0.cmp(&b.0));
+    scripts
+}
+
+pub fn get_local_script_path_for(directory: &str, profile: Option<&str>) -> PathBuf {
+    match profile {
+        Some(profile) => Path::new(directory).join(format!(".tmuxession@{}.sh", encode(profile))),
+        None => Path::new(directory).join(LOCAL_SCRIPT_NAME),
+    }
+}
+
+pub fn get_session_script_path_for(directory: &str, profile: Option<&str>) -> PathBuf {
+    let file_name = match profile {
+        Some(profile) => format!("{}@{}.sh", encode(directory), encode(profile)),
+        None => format!("{}.sh", encode(directory)),
+    };
+    get_data_dir().join(file_name)
+}
+
+/// Directory of the files saved along with a session script, like the
+/// sessions of the editors running in its panes.
+pub fn get_session_files_dir(script_path: &Path) -> PathBuf {
+    script_path.with_extension("d")
+}
+
+/// Splits the file name of a script in the data directory into the directory
+/// and the profile it was saved for.
+pub fn parse_script_file_name(file_name: &str) -> Option<(String, Option<String>)> {
+    let stem = file_name.strip_suffix(".sh")?;
+    let (encoded_directory, encoded_profile) = match stem.split_once('@') {
+        Some((directory, profile)) => (directory, Some(profile)),
+        None => (stem, None),
+    };
+    let directory = decode(encoded_directory).ok()?.to_string();
+    let profile = match encoded_profile {
+        Some(profile) => Some(decode(profile).ok()?.to_string()),
+        None => None,
     };
-    let file_name = encode(&current_dir);
-    xdg_dirs
-        .place_data_file(format!("{}.sh", file_name))
-        .unwrap()
+    Some((directory, profile))
+}
+
+/// The profile a script was saved under, in the data directory or in the
+/// project.
+pub fn get_script_profile(script_path: &Path) -> Option<String> {
+    let file_name = script_path.file_name()?.to_str()?;
+    match parse_local_script_file_name(file_name) {
+        Some(profile) => profile,
+        None => parse_script_file_name(file_name)?.1,
+    }
+}
+
+/// Whether a script is kept inside the project instead of the data directory.
+pub fn is_local_script(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| parse_local_script_file_name(&name.to_string_lossy()).is_some())
+}
+
+fn parse_local_script_file_name(file_name: &str) -> Option<Option<String>> {
+    if file_name == LOCAL_SCRIPT_NAME {
+        return Some(None);
+    }
+    let profile = file_name
+        .strip_prefix(".tmuxession@")?
+        .strip_suffix(".sh")?;
+    Some(Some(decode(profile).ok()?.to_string()))
 }
 
 pub fn is_inside_tmux() -> bool {
-    let inside_tmux = env::var("TMUX").is_ok()
+    env::var("TMUX").is_ok()
         || env::var("TERM_PROGRAM").unwrap_or_default() == "tmux"
-        || env::var("TMUX_PANE").is_ok();
-
-    inside_tmux
+        || env::var("TMUX_PANE").is_ok()
 }
 
 pub fn capture_session_name_from_script(shell_script: &str) -> String {
     // Return the session_name value
     TMUX_SESSION_RE
-        .captures(&shell_script)
+        .captures(shell_script)
         .and_then(|caps| caps.get(1))
         .map_or("", |m| m.as_str().trim())
         .to_string()
 }
+
+pub fn shell_quote(value: &str) -> String {
+    // Wrap in single quotes, closing and escaping any embedded single quote
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+pub fn capture_pane_cwds_from_script(shell_script: &str, root: &str) -> Vec<String> {
+    TMUX_PANE_CWD_RE
+        .captures_iter(shell_script)
+        .filter_map(|caps| caps.get(1))
+        .map(|m| expand_cwd(m.as_str(), root))
+        .collect()
+}
+
+/// Directory of the session a script in the data directory or a project-local
+/// script was saved for.
+pub fn get_session_directory(script_path: &Path) -> Option<String> {
+    let file_name = script_path.file_name()?.to_str()?;
+    if parse_local_script_file_name(file_name).is_some() {
+        return Some(script_path.parent()?.to_string_lossy().to_string());
+    }
+    if script_path.parent()? != get_data_dir() {
+        return None;
+    }
+    parse_script_file_name(file_name).map(|(directory, _)| directory)
+}
+
+/// Turns a pane cwd into a shell word that doesn't depend on the current
+/// user or the location of the project, using `$PROJECT_ROOT` for paths under
+/// the session's directory and `~` for paths under `$HOME`.
+pub fn template_cwd(cwd: &str, root: &str) -> String {
+    if let Some(rest) = strip_dir_prefix(cwd, root) {
+        return format!("\"$PROJECT_ROOT\"{}", shell_word(rest));
+    }
+    if let Ok(home) = env::var("HOME") {
+        if let Some(rest) = strip_dir_prefix(cwd, &home) {
+            return format!("~{}", shell_word(rest));
+        }
+    }
+    shell_word(cwd)
+}
+
+/// Reverses `template_cwd`, expanding the placeholders for the current user
+/// and the given session directory.
+pub fn expand_cwd(word: &str, root: &str) -> String {
+    if let Some(rest) = word.strip_prefix("\"$PROJECT_ROOT\"") {
+        return format!("{}{}", root, unquote_shell_word(rest));
+    }
+    if let Some(rest) = word.strip_prefix('~') {
+        if rest.is_empty() || rest.starts_with('/') {
+            let home = env::var("HOME").unwrap_or_default();
+            return format!("{}{}", home, unquote_shell_word(rest));
+        }
+    }
+    unquote_shell_word(word)
+}
+
+pub fn strip_dir_prefix<'a>(path: &'a str, dir: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(dir)?;
+    if dir.is_empty() || !(rest.is_empty() || rest.starts_with('/')) {
+        return None;
+    }
+    Some(rest)
+}
+
+/// Quotes `value` for the shell only when it contains special characters.
+pub fn shell_word(value: &str) -> String {
+    let is_safe = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "/._-+=:,@%".contains(c));
+    if is_safe {
+        return value.to_string();
+    }
+    double_quote(value)
+}
+
+/// Wraps `value` in double quotes, escaping the characters the shell would
+/// still expand.
+pub fn double_quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if "\"\\$`".contains(c) {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn unquote_shell_word(word: &str) -> String {
+    let mut value = String::new();
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '\\') => {
+                if let Some(escaped) = chars.next() {
+                    value.push(escaped);
+                }
+            }
+            // Inside double quotes, the backslash only escapes what would
+            // be expanded otherwise
+            (Some('"'), '\\') => match chars.next() {
+                Some(escaped) if "\"\\$`".contains(escaped) => value.push(escaped),
+                Some(other) => {
+                    value.push('\\');
+                    value.push(other);
+                }
+                None => value.push('\\'),
+            },
+            (_, c) => value.push(c),
+        }
+    }
+    value
+}
+
+/// The shells completions and the history hook are printed for
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// The user's shell, which panes without a captured command run.
+pub fn get_default_shell() -> String {
+    env::var("SHELL").unwrap_or("sh".to_string())
+}
+
+/// Whether `command` only starts an interactive shell.
+pub fn is_shell_command(command: &str) -> bool {
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let program = program.rsplit('/').next().unwrap_or_default();
+    let program = program.strip_prefix('-').unwrap_or(program);
+    [
+        "sh", "bash", "zsh", "fish", "dash", "ksh", "tcsh", "csh", "nu",
+    ]
+    .contains(&program)
+        && words.all(|arg| arg.starts_with('-'))
+}
+
+pub fn is_session_stale(session: &SavedSession) -> bool {
+    if !Path::new(&session.directory).is_dir() {
+        return true;
+    }
+
+    // The directory is still there, but none of the panes would be restored
+    // in a directory that exists
+    let shell_script = fs::read_to_string(&session.path).unwrap_or_default();
+    let cwds = capture_pane_cwds_from_script(&shell_script, &session.directory);
+    !cwds.is_empty() && cwds.iter().all(|cwd| !Path::new(cwd).is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pane_line(cwd: &str) -> String {
+        format!(
+            "pane_0_0=$(tmux new-window -t \"$session_name\" -c {} -P -F '#{{pane_id}}' \"bash\")\n",
+            cwd
+        )
+    }
+
+    #[test]
+    fn sessions_are_stale_when_nothing_would_be_restored_in_place() {
+        let dir = env::temp_dir().join(format!("tmuxession-stale-{}", std::process::id()));
+        let root = dir.join("project");
+        fs::create_dir_all(root.join("api")).unwrap();
+        let root = root.to_string_lossy().to_string();
+
+        let is_stale = |directory: &str, shell_script: &str| {
+            let path = dir.join("session.sh");
+            fs::write(&path, shell_script).unwrap();
+            is_session_stale(&SavedSession {
+                path,
+                name: "web".to_string(),
+                directory: directory.to_string(),
+                profile: None,
+            })
+        };
+
+        let missing_dir = format!("{}/gone", root);
+        assert!(is_stale(&missing_dir, &pane_line("\"$PROJECT_ROOT\"")));
+        assert!(is_stale(&root, &pane_line("\"$PROJECT_ROOT\"/gone")));
+        assert!(!is_stale(
+            &root,
+            &[
+                pane_line("\"$PROJECT_ROOT\"/gone"),
+                pane_line("\"$PROJECT_ROOT\"/api")
+            ]
+            .concat()
+        ));
+        // Scripts whose panes can't be found are only stale with their directory
+        assert!(!is_stale(&root, "tmux new-session -d -s web\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use crate::common::Shell;
+use crate::list_sessions::{get_saved_sessions, get_session_label};
+use clap_complete::env::Shells;
+use clap_complete::CompletionCandidate;
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+/// Prints the script registering the completions in the shell. The script
+/// calls back into tmuxession, so that saved sessions can be completed.
+pub fn print_completions(shell: Shell) {
+    let name = match shell {
+        Shell::Bash => "bash",
+        Shell::Zsh => "zsh",
+        Shell::Fish => "fish",
+    };
+    let shells = Shells::builtins();
+    shells
+        .completer(name)
+        .unwrap()
+        .write_registration(
+            "COMPLETE",
+            "tmuxession",
+            "tmuxession",
+            "tmuxession",
+            &mut io::stdout(),
+        )
+        .expect("Could not write the completions");
+}
+
+pub fn print_man_page(command: clap::Command) {
+    clap_mangen::Man::new(command)
+        .render(&mut io::stdout())
+        .expect("Could not write the man page");
+}
+
+/// The data directory given in the command line being completed, or in the
+/// environment, as the completers run before the command line is parsed.
+pub fn get_completed_data_dir() -> Option<PathBuf> {
+    let args: Vec<String> = env::args().collect();
+    let mut data_dir = None;
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--data-dir" {
+            data_dir = args.get(i + 1).cloned();
+        } else if let Some(value) = arg.strip_prefix("--data-dir=") {
+            data_dir = Some(value.to_string());
+        }
+    }
+    data_dir
+        .or_else(|| env::var("TMUXESSION_DATA_DIR").ok())
+        .map(PathBuf::from)
+}
+
+/// Session names, for the commands that take a session or a directory.
+pub fn complete_sessions() -> Vec<CompletionCandidate> {
+    let mut sessions = get_saved_sessions();
+    // The profiles of a directory usually share the session name
+    sessions.sort_by(|a, b| a.name.cmp(&b.name));
+    sessions.dedup_by(|a, b| a.name == b.name);
+    sessions
+        .iter()
+        .map(|session| {
+            CompletionCandidate::new(&session.name).help(Some(session.directory.clone().into()))
+        })
+        .collect()
+}
+
+pub fn complete_directories() -> Vec<CompletionCandidate> {
+    get_saved_sessions()
+        .iter()
+        .map(|session| {
+            CompletionCandidate::new(&session.directory).help(Some(session.name.clone().into()))
+        })
+        .collect()
+}
+
+pub fn complete_profiles() -> Vec<CompletionCandidate> {
+    let mut profiles: Vec<String> = get_saved_sessions()
+        .into_iter()
+        .filter_map(|session| session.profile)
+        .collect();
+    profiles.sort();
+    profiles.dedup();
+    profiles.into_iter().map(CompletionCandidate::new).collect()
+}
+
+pub fn complete_scripts() -> Vec<CompletionCandidate> {
+    get_saved_sessions()
+        .iter()
+        .map(|session| {
+            CompletionCandidate::new(&session.path).help(Some(get_session_label(session).into()))
+        })
+        .collect()
+}
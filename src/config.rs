@@ -0,0 +1,421 @@
+use crate::common::{get_data_dir, is_shell_command};
+use crossterm::style::Color;
+use regex::Regex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use xdg::BaseDirectories;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory where the session scripts are saved, defaults to the XDG
+    /// data directory (usually ~/.local/share/tmuxession)
+    pub data_dir: Option<PathBuf>,
+    /// Name of the tmux server socket, like `tmux -L`
+    pub socket_name: Option<String>,
+    /// Path of the tmux server socket, like `tmux -S`
+    pub socket_path: Option<PathBuf>,
+    /// Editor used by `tmuxession edit` when $EDITOR is not set
+    pub editor: String,
+    /// Shell the session scripts are run with on restore, also used in the
+    /// shebang of the generated scripts
+    pub shell: String,
+    pub restore: RestoreConfig,
+    pub capture: CaptureConfig,
+    pub stop: StopConfig,
+    pub ui: UiConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RestoreConfig {
+    /// What to do when a session with the same name is already running
+    pub mode: RestoreMode,
+    /// Scrollback history limit of the panes of restored sessions
+    pub history_limit: Option<u32>,
+    /// Seconds to wait for a pane with startup annotations to be ready,
+    /// unless the annotation gives its own timeout
+    pub startup_timeout: u64,
+    /// Type the commands of the panes without running them
+    pub prefill: bool,
+    /// Regexes of the commands that are typed without being run
+    pub prefill_commands: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    /// Ask what to do
+    Ask,
+    /// Attach or switch to the running session
+    Attach,
+    /// Kill the running session and restore the saved one
+    Replace,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Regexes of the only commands that are captured, everything is
+    /// captured when empty
+    pub allow_commands: Vec<Pattern>,
+    /// Regexes of commands that are never captured
+    pub deny_commands: Vec<Pattern>,
+    /// Session environment variables that are captured, a trailing `*`
+    /// matches any variable with that prefix
+    pub environment: Vec<String>,
+    /// Rewrites of the command in the foreground of a pane into the command
+    /// that was typed, the first rule matching the command is used
+    pub wrappers: Vec<CommandRewrite>,
+    /// Seconds vim gets to write its session after the keys are sent
+    pub editor_timeout: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandRewrite {
+    /// Regex matching the command line of the process
+    pub command: Pattern,
+    /// The captured command, `$1`, `$2`... being the groups of the regex
+    pub replace: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StopConfig {
+    /// Seconds to wait for the panes to exit before killing the session
+    pub timeout: u64,
+    /// Keys sent to the panes to make their command exit, the first rule
+    /// matching the command of a pane is used
+    pub keys: Vec<StopKeys>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StopKeys {
+    /// Regex matching the command running in the pane
+    pub command: Pattern,
+    /// Keys as given to `tmux send-keys`
+    pub keys: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    /// Color of the selected option
+    pub selected_color: String,
+    /// Color of the headers grouping the options
+    pub header_color: String,
+}
+
+/// A regex of the config file, compiled once when the config is loaded.
+#[derive(Debug)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    fn new(pattern: &str) -> Self {
+        Pattern(Regex::new(pattern).unwrap())
+    }
+}
+
+impl std::ops::Deref for Pattern {
+    type Target = Regex;
+
+    fn deref(&self) -> &Regex {
+        &self.0
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern).map(Pattern).map_err(|err| {
+            D::Error::custom(format!("invalid command pattern `{}`: {}", pattern, err))
+        })
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            data_dir: None,
+            socket_name: None,
+            socket_path: None,
+            editor: "vi".to_string(),
+            shell: "/bin/bash".to_string(),
+            restore: RestoreConfig::default(),
+            capture: CaptureConfig::default(),
+            stop: StopConfig::default(),
+            ui: UiConfig::default(),
+        }
+    }
+}
+
+impl Default for RestoreConfig {
+    fn default() -> Self {
+        RestoreConfig {
+            mode: RestoreMode::Ask,
+            history_limit: None,
+            startup_timeout: 60,
+            prefill: false,
+            prefill_commands: vec![],
+        }
+    }
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        let rewrite = |command: &str, replace: &str| CommandRewrite {
+            command: Pattern::new(command),
+            replace: replace.to_string(),
+        };
+        CaptureConfig {
+            allow_commands: vec![],
+            deny_commands: vec![],
+            environment: vec![],
+            wrappers: vec![
+                // Scripts show up with the interpreter running them
+                rewrite(r"^\S*python[0-9.]* \S*/(poetry|pipenv|pdm) (.*)$", "$1 $2"),
+                rewrite(
+                    r"^\S*node \S*/(npm|npx|yarn|pnpm)(?:-cli)?(?:\.c?js)? (.*)$",
+                    "$1 $2",
+                ),
+                // The environment is loaded again by direnv in the directory
+                rewrite(r"^direnv exec \S+ (.*)$", "$1"),
+            ],
+            editor_timeout: 5,
+        }
+    }
+}
+
+impl RestoreConfig {
+    /// Whether the last command of a pane is typed without being run, panes
+    /// only running a shell have nothing to type.
+    pub fn is_pane_prefilled(&self, commands: &[String]) -> bool {
+        let Some(command) = commands.last().filter(|command| !is_shell_command(command)) else {
+            return false;
+        };
+        self.prefill
+            || self
+                .prefill_commands
+                .iter()
+                .any(|pattern| pattern.is_match(command))
+    }
+}
+
+impl Default for StopConfig {
+    fn default() -> Self {
+        let stop_keys = |command: &str, keys: &[&str]| StopKeys {
+            command: Pattern::new(command),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+        };
+        StopConfig {
+            timeout: 10,
+            keys: vec![
+                stop_keys("^n?vim?$", &["Escape", ":wqa", "Enter"]),
+                stop_keys("^(ba|z|fi|da|k|tc|c)?sh$", &["C-u", "exit", "Enter"]),
+                stop_keys("^(less|man|htop|top)$", &["q"]),
+                stop_keys("", &["C-c"]),
+            ],
+        }
+    }
+}
+
+impl StopConfig {
+    pub fn get_keys(&self, command: &str) -> Option<&Vec<String>> {
+        self.keys
+            .iter()
+            .find(|rule| rule.command.is_match(command))
+            .map(|rule| &rule.keys)
+    }
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig {
+            selected_color: "green".to_string(),
+            header_color: "grey".to_string(),
+        }
+    }
+}
+
+impl CaptureConfig {
+    pub fn is_command_captured(&self, command: &str) -> bool {
+        let matches =
+            |patterns: &Vec<Pattern>| patterns.iter().any(|pattern| pattern.is_match(command));
+
+        (self.allow_commands.is_empty() || matches(&self.allow_commands))
+            && !matches(&self.deny_commands)
+    }
+
+    pub fn rewrite_command(&self, command: &str) -> String {
+        self.wrappers
+            .iter()
+            .find(|rule| rule.command.is_match(command))
+            .map_or(command.to_string(), |rule| {
+                rule.command
+                    .replace(command, rule.replace.as_str())
+                    .to_string()
+            })
+    }
+
+    pub fn is_environment_captured(&self, name: &str) -> bool {
+        self.environment
+            .iter()
+            .any(|rule| match rule.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == rule,
+            })
+    }
+}
+
+pub fn get_config() -> &'static Config {
+    CONFIG.get_or_init(load_config)
+}
+
+/// Uses the default config in the tests, whatever the config file of the
+/// user running them has.
+#[cfg(test)]
+pub fn init_test_config() {
+    let _ = CONFIG.set(Config::default());
+}
+
+/// Loads the config file, overriding it with the options given on the command
+/// line. Must be called before the config is first used.
+pub fn init_config(
+    data_dir: Option<PathBuf>,
+    socket_name: Option<String>,
+    socket_path: Option<PathBuf>,
+) {
+    let mut config = load_config();
+
+    // Empty values come from environment variables that are set but empty
+    if let Some(data_dir) = data_dir.filter(|path| !path.as_os_str().is_empty()) {
+        config.data_dir = Some(data_dir);
+    }
+    if let Some(socket_name) = socket_name.filter(|name| !name.is_empty()) {
+        config.socket_name = Some(socket_name);
+        config.socket_path = None;
+    }
+    if let Some(socket_path) = socket_path.filter(|path| !path.as_os_str().is_empty()) {
+        config.socket_path = Some(socket_path);
+        config.socket_name = None;
+    }
+
+    CONFIG
+        .set(config)
+        .expect("The config has already been initialized");
+}
+
+pub fn get_config_path() -> PathBuf {
+    let xdg_dirs = BaseDirectories::with_prefix("tmuxession").unwrap();
+    xdg_dirs.get_config_home().join("config.toml")
+}
+
+fn load_config() -> Config {
+    let config_path = get_config_path();
+    let content = match fs::read_to_string(&config_path) {
+        Ok(content) => content,
+        Err(_) => return Config::default(),
+    };
+
+    let config: Config = match toml::from_str(&content) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!(
+                "Error: Invalid config file {}: {}",
+                config_path.display(),
+                err
+            );
+            std::process::exit(1);
+        }
+    };
+
+    for color in [&config.ui.selected_color, &config.ui.header_color] {
+        if Color::try_from(color.as_str()).is_err() {
+            eprintln!("Error: Invalid color `{}` in the config file", color);
+            std::process::exit(1);
+        }
+    }
+
+    config
+}
+
+pub fn print_config() {
+    let config = get_config();
+    let config_path = get_config_path();
+
+    if config_path.exists() {
+        println!("# Loaded from {}", config_path.display());
+    } else {
+        println!(
+            "# No config file found at {}, using the defaults",
+            config_path.display()
+        );
+    }
+    println!("# Data directory: {}", get_data_dir().display());
+    println!("{}", toml::to_string_pretty(config).unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_command_patterns() {
+        let config: Config = toml::from_str(
+            r#"
+            [capture]
+            deny_commands = ["^rm "]
+            wrappers = [{ command = '^direnv exec \S+ (.*)$', replace = "$1" }]
+            "#,
+        )
+        .unwrap();
+
+        assert!(!config.capture.is_command_captured("rm -rf target"));
+        assert!(config.capture.is_command_captured("cargo watch"));
+        assert_eq!(
+            config
+                .capture
+                .rewrite_command("direnv exec /srv/web npm start"),
+            "npm start"
+        );
+        assert_eq!(config.stop.get_keys("htop"), Some(&vec!["q".to_string()]));
+        assert!(toml::to_string(&config)
+            .unwrap()
+            .contains(r#"deny_commands = ["^rm "]"#));
+    }
+
+    #[test]
+    fn prefills_the_matching_commands() {
+        let config: Config = toml::from_str("[restore]\nprefill_commands = [\"^rm \"]").unwrap();
+        let commands = |commands: &[&str]| -> Vec<String> {
+            commands.iter().map(|command| command.to_string()).collect()
+        };
+
+        assert!(config
+            .restore
+            .is_pane_prefilled(&commands(&["bash", "rm -r dist"])));
+        assert!(!config
+            .restore
+            .is_pane_prefilled(&commands(&["bash", "npm start"])));
+        assert!(!config.restore.is_pane_prefilled(&commands(&["bash"])));
+    }
+
+    #[test]
+    fn rejects_invalid_command_patterns() {
+        let err = toml::from_str::<Config>("[restore]\nprefill_commands = [\"(\"]").unwrap_err();
+        assert!(err.message().starts_with("invalid command pattern `(`"));
+    }
+}
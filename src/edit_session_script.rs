@@ -1,8 +1,11 @@
-use crate::common::get_session_script_path;
+use crate::common::find_session_script_path;
+use crate::config::get_config;
+use crate::session_file::{has_checksum, remove_checksum, verify_checksum, write_file_atomically};
 use std::env;
+use std::fs;
 
-pub fn edit_session_script() {
-    let file_path = get_session_script_path();
+pub fn edit_session_script(profile: Option<String>) {
+    let file_path = find_session_script_path(profile.as_deref());
 
     if !file_path.exists() {
         eprintln!(
@@ -12,9 +15,24 @@ pub fn edit_session_script() {
         std::process::exit(1);
     }
 
-    let editor = env::var("EDITOR").unwrap_or("vi".to_string());
+    let original_script = fs::read_to_string(&file_path).unwrap_or_default();
+    let editor = env::var("EDITOR").unwrap_or(get_config().editor.clone());
     std::process::Command::new(editor)
-        .arg(file_path)
+        .arg(&file_path)
         .status()
         .expect("Failed to open the editor");
+
+    // The checksum only vouches for scripts as tmuxession wrote them, so it is
+    // removed from an edited script instead of being updated. A script that
+    // didn't match it already is left for `restore --force`.
+    let shell_script = fs::read_to_string(&file_path).unwrap_or_default();
+    if has_checksum(&shell_script)
+        && verify_checksum(&original_script)
+        && !verify_checksum(&shell_script)
+    {
+        if let Err(err) = write_file_atomically(&file_path, &remove_checksum(&shell_script)) {
+            eprintln!("Error: Could not update {}: {}", file_path.display(), err);
+            std::process::exit(1);
+        }
+    }
 }
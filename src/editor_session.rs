@@ -0,0 +1,312 @@
+use crate::common::{double_quote, shell_quote, strip_dir_prefix, TmuxWindow};
+use crate::config::get_config;
+use crate::startup::get_pane_key;
+use crate::tmux_commands::tmux_command;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref EDITOR_RE: Regex = Regex::new(r"^(?:\S*/)?(n?vim)(?:\s|$)").unwrap();
+    static ref EDITOR_SESSION_RE: Regex = Regex::new(r"^pane_\d+_\d+\.vim$").unwrap();
+}
+
+/// Writes a vim session for every pane running vim or neovim to `files_dir`,
+/// and makes the panes start the editor with it. Sessions of panes that no
+/// longer run an editor are removed. Sessions inside `root` are given relative
+/// to it, for the script to work wherever the project is.
+pub fn save_editor_sessions(
+    windows: &mut [TmuxWindow],
+    target: Option<&str>,
+    files_dir: &Path,
+    root: &str,
+) {
+    let mut saved: Vec<String> = vec![];
+    for (w, window) in windows.iter_mut().enumerate() {
+        for (p, pane) in window.panes.iter_mut().enumerate() {
+            let Some(command) = pane.commands.last_mut() else {
+                continue;
+            };
+            let Some(caps) = EDITOR_RE.captures(command) else {
+                continue;
+            };
+            let editor = caps[1].to_string();
+
+            let pane_target = match target {
+                Some(target) => format!("{}:{}.{}", target, &window.id, &pane.id),
+                None => format!("{}.{}", &window.id, &pane.id),
+            };
+            let file_name = format!("pane_{}_{}.vim", w, p);
+            let path = files_dir.join(&file_name);
+            let result = fs::create_dir_all(files_dir)
+                .map_err(|err| err.to_string())
+                .and_then(|_| write_vim_session(&editor, &pane_target, &path))
+                .and_then(|_| match strip_dir_prefix(&path.to_string_lossy(), root) {
+                    Some(_) => template_session_dirs(&path, root),
+                    None => Ok(()),
+                });
+            match result {
+                Ok(_) => {
+                    *command = format!("{} -S {}", editor, get_session_path_word(&path, root));
+                    saved.push(file_name);
+                }
+                Err(err) => eprintln!(
+                    "Warning: Could not save the {} session of pane {}: {}",
+                    editor,
+                    get_pane_key(&window.name, p),
+                    err
+                ),
+            }
+        }
+    }
+
+    remove_stale_sessions(files_dir, &saved);
+}
+
+fn write_vim_session(editor: &str, pane_target: &str, path: &Path) -> Result<(), String> {
+    let _ = fs::remove_file(path);
+
+    // Neovim can be asked directly, without typing into the pane
+    if editor == "nvim" {
+        if let Some(server) = find_nvim_server(pane_target) {
+            let expr = format!(
+                "execute('mksession! ' .. fnameescape({}))",
+                vim_string(&path.to_string_lossy())
+            );
+            let status = Command::new("nvim")
+                .args(["--server", &server, "--remote-expr", &expr])
+                .output()
+                .map(|output| output.status);
+            if status.is_ok_and(|status| status.success()) && path.exists() {
+                return Ok(());
+            }
+        }
+    }
+
+    // The keys are only typed into the editor itself, not into a shell it
+    // was suspended from or a command it runs
+    let output = tmux_command()
+        .args(["display-message", "-p", "-t", pane_target])
+        .arg("#{pane_current_command}")
+        .output()
+        .expect("Failed to execute tmux command");
+    let foreground = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !foreground.starts_with(editor) {
+        return Err(format!("{} is not in the foreground", editor));
+    }
+
+    // Copy mode is left first, it would get the keys otherwise, and CTRL-\
+    // CTRL-N goes back to normal mode from any mode of vim
+    let command = format!(":mksession! {}", escape_vim_path(&path.to_string_lossy()));
+    let keys = [
+        vec!["-X", "cancel"],
+        vec!["C-\\", "C-n"],
+        vec!["-l", &command],
+        vec!["Enter"],
+    ];
+    for keys in keys {
+        tmux_command()
+            .args(["send-keys", "-t", pane_target])
+            .args(keys)
+            .output()
+            .expect("Failed to execute tmux command");
+    }
+
+    let timeout = get_config().capture.editor_timeout;
+    let start = Instant::now();
+    while !path.exists() {
+        if start.elapsed() >= Duration::from_secs(timeout) {
+            return Err(format!("timed out after {}s", timeout));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+/// The RPC socket of the neovim running in the pane, in the default location
+/// neovim listens on.
+fn find_nvim_server(pane_target: &str) -> Option<String> {
+    let output = tmux_command()
+        .args(["display-message", "-p", "-t", pane_target, "#{pane_pid}"])
+        .output()
+        .ok()?;
+    let pane_pid = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let output = Command::new("ps")
+        .args(["-e", "-o", "pid=,ppid=,comm="])
+        .output()
+        .ok()?;
+    let processes: Vec<(String, String, String)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((
+                fields.next()?.to_string(),
+                fields.next()?.to_string(),
+                fields.next()?.to_string(),
+            ))
+        })
+        .collect();
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (pid, ppid, _) in &processes {
+        children.entry(ppid).or_default().push(pid);
+    }
+
+    // The neovim TUI runs its server in a child process
+    let mut pids = vec![pane_pid.as_str()];
+    let mut nvim_pids = vec![];
+    while let Some(pid) = pids.pop() {
+        if processes
+            .iter()
+            .any(|(other, _, comm)| other == pid && comm == "nvim")
+        {
+            nvim_pids.push(pid);
+        }
+        pids.extend(children.get(pid).into_iter().flatten());
+    }
+
+    let mut run_dirs: Vec<PathBuf> = vec![];
+    if let Ok(runtime_dir) = env::var("XDG_RUNTIME_DIR") {
+        run_dirs.push(PathBuf::from(runtime_dir));
+    }
+    let user = env::var("USER").unwrap_or_default();
+    if let Ok(entries) = fs::read_dir(env::temp_dir().join(format!("nvim.{}", user))) {
+        run_dirs.extend(entries.flatten().map(|entry| entry.path()));
+    }
+
+    nvim_pids.iter().find_map(|pid| {
+        run_dirs
+            .iter()
+            .map(|dir| dir.join(format!("nvim.{}.0", pid)))
+            .find(|socket| socket.exists())
+            .map(|socket| socket.to_string_lossy().to_string())
+    })
+}
+
+fn remove_stale_sessions(files_dir: &Path, saved: &[String]) {
+    let Ok(entries) = fs::read_dir(files_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if EDITOR_SESSION_RE.is_match(&file_name) && !saved.contains(&file_name) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    // Only removed when nothing else is kept there
+    let _ = fs::remove_dir(files_dir);
+}
+
+/// Points the editor sessions of a script to the files directory of the
+/// script it is moved to.
+pub fn relocate_editor_sessions(shell_script: &str, old_dir: &Path, new_dir: &Path) -> String {
+    // The directory as written in the double quoted commands of the script,
+    // without the quotes closed after the file name
+    let quote_dir = |dir: &Path| {
+        let quoted = double_quote(&quote_session_path(&dir.join("")));
+        quoted[1..quoted.len() - 2].to_string()
+    };
+    shell_script.replace(&quote_dir(old_dir), &quote_dir(new_dir))
+}
+
+/// The session path as given to `-S`, which vim sources without escaping
+/// the `%` of the URL encoded directories.
+/// Makes the directories a session inside the project changes to relative to
+/// it, like the session itself.
+fn template_session_dirs(path: &Path, root: &str) -> Result<(), String> {
+    let session = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let escaped_root = escape_vim_path(root);
+    let lines: Vec<String> = session
+        .lines()
+        .map(|line| {
+            let Some((command, dir)) = line.split_once(' ') else {
+                return line.to_string();
+            };
+            match strip_dir_prefix(dir, &escaped_root) {
+                Some(rest) if ["cd", "lcd", "tcd"].contains(&command) => format!(
+                    "exe '{}' fnameescape($PROJECT_ROOT) . {}",
+                    command,
+                    vim_string(rest)
+                ),
+                _ => line.to_string(),
+            }
+        })
+        .collect();
+    fs::write(path, lines.join("\n") + "\n").map_err(|err| err.to_string())
+}
+
+/// The session path given to `vim -S`. Vim expands `$PROJECT_ROOT` itself,
+/// from the environment the script gives the pane.
+fn get_session_path_word(path: &Path, root: &str) -> String {
+    match strip_dir_prefix(&path.to_string_lossy(), root) {
+        Some(rest) => shell_quote(&format!("$PROJECT_ROOT{}", escape_vim_path(rest))),
+        None => quote_session_path(path),
+    }
+}
+
+fn quote_session_path(path: &Path) -> String {
+    shell_quote(&escape_vim_path(&path.to_string_lossy()))
+}
+
+fn vim_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Escapes a file name for the vim command line, like `fnameescape()`.
+fn escape_vim_path(path: &str) -> String {
+    let mut escaped = String::new();
+    for c in path.chars() {
+        if " \t\n*?[{`$\\%#'\"|!<".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_to_the_directories_of_the_project_relative_to_it() {
+        let path = env::temp_dir().join(format!("tmuxession-{}.vim", std::process::id()));
+        fs::write(
+            &path,
+            "cd /home/me/my\\ web/api\nbadd +0 notes.txt\nlcd /home/me/my\\ website\ntcd /tmp\n",
+        )
+        .unwrap();
+
+        template_session_dirs(&path, "/home/me/my web").unwrap();
+        let session = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            session,
+            "exe 'cd' fnameescape($PROJECT_ROOT) . '/api'\nbadd +0 notes.txt\n\
+             lcd /home/me/my\\ website\ntcd /tmp\n"
+        );
+    }
+
+    #[test]
+    fn gives_the_sessions_of_the_project_relative_to_it() {
+        let path = Path::new("/home/me/web/.tmuxession.sh.d/pane_0_0.vim");
+        assert_eq!(
+            get_session_path_word(path, "/home/me/web"),
+            "'$PROJECT_ROOT/.tmuxession.sh.d/pane_0_0.vim'"
+        );
+        assert_eq!(
+            get_session_path_word(path, "/home/me/we"),
+            "'/home/me/web/.tmuxession.sh.d/pane_0_0.vim'"
+        );
+        assert_eq!(
+            get_session_path_word(Path::new("/data/%2Fweb.d/pane_0_0.vim"), "/web"),
+            "'/data/\\%2Fweb.d/pane_0_0.vim'"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use crate::common::{
+    is_shell_command, shell_word, SavedSession, TmuxPane, TmuxSession, UserOption,
+};
+use crate::import_config::ConfigFormat;
+use crate::list_sessions::{find_saved_sessions, get_session_label};
+use crate::parse_script::parse_session_script;
+use crate::ui::get_user_option;
+use serde_norway::{Mapping, Value};
+use std::fs;
+use std::path::PathBuf;
+
+pub fn export_config(format: ConfigFormat, query: &str, output: Option<PathBuf>) {
+    let saved_session = select_saved_session(query);
+    let shell_script = fs::read_to_string(&saved_session.path).unwrap_or_else(|err| {
+        eprintln!(
+            "Error: Could not read {}: {}",
+            saved_session.path.display(),
+            err
+        );
+        std::process::exit(1);
+    });
+    let session = parse_session_script(&shell_script, &saved_session.directory);
+
+    let config = match format {
+        ConfigFormat::Tmuxinator => render_tmuxinator(&session),
+        ConfigFormat::Tmuxp => render_tmuxp(&session),
+    };
+    let output = output.unwrap_or_else(|| get_default_output(&session.name));
+    if let Err(err) = fs::write(&output, serde_norway::to_string(&config).unwrap()) {
+        eprintln!("Error: Could not write {}: {}", output.display(), err);
+        std::process::exit(1);
+    }
+
+    println!("Exported `{}` to {}", &session.name, output.display());
+}
+
+/// `<session name>.yml` in the current directory, whatever characters the
+/// session name has.
+fn get_default_output(session_name: &str) -> PathBuf {
+    let file_name: String = session_name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            c => c,
+        })
+        .collect();
+    PathBuf::from(format!("{}.yml", file_name))
+}
+
+fn select_saved_session(query: &str) -> SavedSession {
+    let mut sessions = find_saved_sessions(query);
+    if sessions.len() <= 1 {
+        return sessions.pop().unwrap_or_else(|| {
+            eprintln!("Error: No saved session matches `{}`", query);
+            std::process::exit(1);
+        });
+    }
+
+    let mut options: Vec<UserOption> = sessions
+        .iter()
+        .enumerate()
+        .map(|(i, session)| UserOption {
+            label: format!("[{}] {}", i + 1, get_session_label(session)),
+            keybind: (i as u8 + b'1') as char,
+            header: false,
+        })
+        .collect();
+    options.push(UserOption {
+        label: "[q] Quit".to_string(),
+        keybind: 'q',
+        header: false,
+    });
+
+    let choice = get_user_option("Select the session to export:", options);
+    if choice == 'q' {
+        std::process::exit(0);
+    }
+    let index = (choice as u8).checked_sub(b'1').unwrap();
+    sessions.remove(index as usize)
+}
+
+fn render_tmuxinator(session: &TmuxSession) -> Value {
+    let mut config = Mapping::new();
+    config.insert("name".into(), session.name.clone().into());
+    config.insert("root".into(), session.root.clone().into());
+
+    if let Some(window) = session.windows.iter().find(|window| window.active) {
+        config.insert("startup_window".into(), window.name.clone().into());
+        if let Some((i, pane)) = window
+            .panes
+            .iter()
+            .enumerate()
+            .find(|(_, pane)| pane.active)
+        {
+            let index = pane.id.parse().unwrap_or(i);
+            config.insert("startup_pane".into(), index.into());
+        }
+    }
+
+    let windows = session
+        .windows
+        .iter()
+        .map(|window| {
+            // tmuxinator has no per pane directories, so panes elsewhere
+            // change to theirs first
+            let window_root = &window.panes[0].cwd;
+            let panes = window
+                .panes
+                .iter()
+                .map(|pane| {
+                    let mut commands = get_typed_commands(pane);
+                    if pane.cwd != *window_root {
+                        commands.insert(0, format!("cd {}", shell_word(&pane.cwd)));
+                    }
+                    match commands.len() {
+                        0 => Value::Null,
+                        1 => commands.remove(0).into(),
+                        _ => commands.into(),
+                    }
+                })
+                .collect::<Vec<Value>>();
+
+            let mut definition = Mapping::new();
+            if *window_root != session.root {
+                definition.insert("root".into(), window_root.clone().into());
+            }
+            if !window.layout.is_empty() {
+                definition.insert("layout".into(), window.layout.clone().into());
+            }
+            definition.insert("panes".into(), panes.into());
+
+            let mut entry = Mapping::new();
+            entry.insert(window.name.clone().into(), definition.into());
+            Value::Mapping(entry)
+        })
+        .collect::<Vec<Value>>();
+    config.insert("windows".into(), windows.into());
+
+    Value::Mapping(config)
+}
+
+fn render_tmuxp(session: &TmuxSession) -> Value {
+    let mut config = Mapping::new();
+    config.insert("session_name".into(), session.name.clone().into());
+    config.insert("start_directory".into(), session.root.clone().into());
+
+    if !session.environment.is_empty() {
+        let mut environment = Mapping::new();
+        for (name, value) in &session.environment {
+            environment.insert(name.clone().into(), value.clone().into());
+        }
+        config.insert("environment".into(), environment.into());
+    }
+
+    let windows = session
+        .windows
+        .iter()
+        .map(|window| {
+            let window_root = &window.panes[0].cwd;
+            let panes = window
+                .panes
+                .iter()
+                .map(|pane| {
+                    let commands = get_typed_commands(pane);
+                    let mut definition = Mapping::new();
+                    if !commands.is_empty() {
+                        definition.insert("shell_command".into(), commands.into());
+                    }
+                    if pane.cwd != *window_root {
+                        definition.insert("start_directory".into(), pane.cwd.clone().into());
+                    }
+                    if pane.active {
+                        definition.insert("focus".into(), true.into());
+                    }
+                    if definition.is_empty() {
+                        Value::Null
+                    } else {
+                        Value::Mapping(definition)
+                    }
+                })
+                .collect::<Vec<Value>>();
+
+            let mut definition = Mapping::new();
+            definition.insert("window_name".into(), window.name.clone().into());
+            if *window_root != session.root {
+                definition.insert("start_directory".into(), window_root.clone().into());
+            }
+            if !window.layout.is_empty() {
+                definition.insert("layout".into(), window.layout.clone().into());
+            }
+            if window.active {
+                definition.insert("focus".into(), true.into());
+            }
+            definition.insert("panes".into(), panes.into());
+            Value::Mapping(definition)
+        })
+        .collect::<Vec<Value>>();
+    config.insert("windows".into(), windows.into());
+
+    Value::Mapping(config)
+}
+
+/// The commands to type in the pane's shell. Both tools start panes with the
+/// user's shell, so a captured shell is left out.
+fn get_typed_commands(pane: &TmuxPane) -> Vec<String> {
+    pane.commands
+        .iter()
+        .enumerate()
+        .filter(|(i, command)| *i != 0 || !is_shell_command(command))
+        .map(|(_, command)| command.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::TmuxWindow;
+    use crate::config::init_test_config;
+    use crate::import_config::{convert_tmuxinator, convert_tmuxp};
+    use crate::test_fixtures::{self, pane, window};
+
+    fn session() -> TmuxSession {
+        test_fixtures::session(vec![
+            TmuxWindow {
+                layout: "main-vertical".to_string(),
+                ..window(
+                    "0",
+                    "editor",
+                    false,
+                    vec![pane("0", "/srv/web", true, &["bash", "vim 'a b.txt'"])],
+                )
+            },
+            TmuxWindow {
+                layout: "even-horizontal".to_string(),
+                ..window(
+                    "1",
+                    "servers",
+                    true,
+                    vec![
+                        pane("0", "/srv/web/api", false, &["bash", "npm run dev"]),
+                        pane("1", "/srv/web/db", true, &["bash", "cd data", "make: db"]),
+                        pane("2", "/srv/web/api", false, &["bash"]),
+                    ],
+                )
+            },
+        ])
+    }
+
+    /// The commands typed in each pane of each window.
+    fn get_commands(session: &TmuxSession) -> Vec<Vec<Vec<String>>> {
+        session
+            .windows
+            .iter()
+            .map(|window| window.panes.iter().map(get_typed_commands).collect())
+            .collect()
+    }
+
+    fn read_back(config: &Value) -> Value {
+        init_test_config();
+        serde_norway::from_str(&serde_norway::to_string(config).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn tmuxp_round_trip() {
+        let session = session();
+        let imported = convert_tmuxp(&read_back(&render_tmuxp(&session)));
+
+        assert_eq!(imported.name, "web");
+        assert_eq!(imported.root, "/srv/web");
+        assert_eq!(get_commands(&imported), get_commands(&session));
+        for (imported, window) in imported.windows.iter().zip(&session.windows) {
+            assert_eq!(imported.name, window.name);
+            assert_eq!(imported.layout, window.layout);
+            assert_eq!(imported.active, window.active);
+            for (imported, pane) in imported.panes.iter().zip(&window.panes) {
+                assert_eq!(imported.cwd, pane.cwd);
+                assert_eq!(imported.active, pane.active);
+            }
+        }
+    }
+
+    #[test]
+    fn tmuxinator_round_trip() {
+        let session = session();
+        let config = read_back(&render_tmuxinator(&session));
+        assert_eq!(config["startup_window"].as_str(), Some("servers"));
+        assert_eq!(config["startup_pane"].as_u64(), Some(1));
+
+        let imported = convert_tmuxinator(&config);
+        assert_eq!(imported.name, "web");
+        assert_eq!(imported.root, "/srv/web");
+        let names: Vec<&str> = imported.windows.iter().map(|w| w.name.as_str()).collect();
+        assert_eq!(names, ["editor", "servers"]);
+        assert_eq!(imported.windows[1].panes[0].cwd, "/srv/web/api");
+        // Panes outside of the window's directory change to theirs first
+        assert_eq!(
+            get_commands(&imported)[1],
+            [
+                vec!["npm run dev".to_string()],
+                vec![
+                    "cd /srv/web/db".to_string(),
+                    "cd data".to_string(),
+                    "make: db".to_string()
+                ],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn default_output_is_a_file_name() {
+        assert_eq!(get_default_output("web"), PathBuf::from("web.yml"));
+        assert_eq!(
+            get_default_output("team/web"),
+            PathBuf::from("team_web.yml")
+        );
+        assert_eq!(get_default_output("../web"), PathBuf::from(".._web.yml"));
+    }
+}
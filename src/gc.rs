@@ -0,0 +1,102 @@
+use crate::common::{get_data_dir, get_session_files_dir, is_session_stale, UserOption};
+use crate::list_sessions::{get_saved_sessions, get_session_label, sort_sessions, SortOrder};
+use crate::ui::{get_user_option, get_user_selection};
+use std::fs;
+
+pub fn collect_garbage() {
+    let mut sessions = get_saved_sessions();
+    sessions.retain(is_session_stale);
+    sort_sessions(&mut sessions, SortOrder::Directory);
+
+    if sessions.is_empty() {
+        println!("No stale tmuxession sessions found.");
+        return;
+    }
+
+    let labels = sessions.iter().map(get_session_label).collect();
+    let selection = get_user_selection(
+        "The following sessions refer to directories that no longer exist:",
+        labels,
+    );
+    if selection.is_empty() {
+        println!("No sessions selected.");
+        return;
+    }
+
+    let title = format!(
+        "What should be done with the {} selected sessions?",
+        selection.len()
+    );
+    let options = vec![
+        UserOption {
+            keybind: 'D',
+            label: "[D]elete the session scripts".to_string(),
+            header: false,
+        },
+        UserOption {
+            keybind: 'A',
+            label: "[A]rchive the session scripts".to_string(),
+            header: false,
+        },
+        UserOption {
+            keybind: 'q',
+            label: "[q]uit".to_string(),
+            header: false,
+        },
+    ];
+    let action = get_user_option(&title, options);
+    if action == 'q' {
+        return;
+    }
+
+    let archive_dir = get_data_dir().join("archive");
+    let verb = if action == 'D' { "delete" } else { "archive" };
+    for index in selection {
+        let session = &sessions[index];
+
+        // The files saved along with the script go with it
+        let files_dir = get_session_files_dir(&session.path);
+        if files_dir.is_dir() {
+            let result = match action {
+                'D' => fs::remove_dir_all(&files_dir),
+                _ => fs::create_dir_all(&archive_dir).and_then(|_| {
+                    fs::rename(&files_dir, archive_dir.join(files_dir.file_name().unwrap()))
+                }),
+            };
+            if let Err(err) = result {
+                eprintln!("Error: Could not {} {}: {}", verb, files_dir.display(), err);
+            }
+        }
+
+        let result = match action {
+            'D' => fs::remove_file(&session.path),
+            'A' => fs::create_dir_all(&archive_dir).and_then(|_| {
+                fs::rename(
+                    &session.path,
+                    archive_dir.join(session.path.file_name().unwrap()),
+                )
+            }),
+            _ => unreachable!(),
+        };
+
+        match result {
+            Ok(_) if action == 'D' => {
+                println!("Deleted `{}`: {}", &session.name, &session.directory)
+            }
+            Ok(_) => println!("Archived `{}`: {}", &session.name, &session.directory),
+            Err(err) => eprintln!(
+                "Error: Could not {} {}: {}",
+                verb,
+                &session.path.display(),
+                err
+            ),
+        }
+    }
+
+    if action == 'A' {
+        println!(
+            "Archived session scripts are kept under: {}",
+            archive_dir.display()
+        );
+    }
+}
@@ -1,44 +1,154 @@
-use crate::common::TmuxSession;
+use crate::common::{
+    double_quote, get_default_shell, is_shell_command, shell_quote, shell_word, template_cwd,
+    TmuxPane, TmuxSession,
+};
+use crate::config::get_config;
+use crate::hooks::format_hook;
+use crate::remote_pane::format_ssh_command;
+use crate::shell_history::format_history_variables;
+use crate::startup::{format_startup_annotation, get_pane_key, PANE_KEY_OPTION};
+
+/// Pane option the command typed in a pane without being run is kept in, for
+/// `tmuxession save` to keep typing it
+pub const PREFILL_OPTION: &str = "@tmuxession_prefill";
 
 pub fn generate_tmux_session_script(session: &TmuxSession) -> String {
     let session_name = session.name.clone();
     let mut shell_script = String::new();
 
     // Create the session
-    shell_script.push_str("#!/bin/bash\n\n");
+    shell_script.push_str(&format!("#!{}\n\n", &get_config().shell));
     shell_script.push_str("###############################################\n");
     shell_script.push_str("# Script generated by tmuxession              #\n");
     shell_script.push_str("###############################################\n\n");
     shell_script.push_str("# Exit on error or unset variable\n");
     shell_script.push_str("set -e\nset -u\n\n\n");
     shell_script.push_str("# Session name\n");
-    shell_script.push_str(format!("session_name={}\n\n\n", &session_name).as_str());
+    shell_script.push_str(format!("session_name={}\n\n", &session_name).as_str());
+    shell_script.push_str("# Directory the session was saved for, pane cwds are relative to it\n");
+    shell_script.push_str("PROJECT_ROOT=\"${PROJECT_ROOT:-$PWD}\"\n\n\n");
+
+    if !session.startup.is_empty() {
+        shell_script.push_str("### Panes whose commands `tmuxession restore` runs in order\n");
+        for startup in &session.startup {
+            shell_script.push_str(&format_startup_annotation(startup));
+            shell_script.push('\n');
+        }
+        shell_script.push_str("\n\n");
+    }
+    if !session.hooks.is_empty() {
+        shell_script.push_str("### Commands run by tmuxession on restore and save\n");
+        for hook in &session.hooks {
+            shell_script.push_str(&format_hook(hook));
+            shell_script.push('\n');
+        }
+        shell_script.push_str("\n\n");
+    }
+    shell_script.push_str("# Commands of the panes listed in $TMUXESSION_DEFER_STARTUP are left\n");
+    shell_script.push_str("# to `tmuxession restore`\n");
+    shell_script.push_str("deferred() {\n");
+    shell_script
+        .push_str("    printf '%s\\n' \"${TMUXESSION_DEFER_STARTUP:-}\" | grep -qxF -- \"$1\"\n");
+    shell_script.push_str("}\n\n\n");
+    let config = get_config();
+    if config.socket_name.is_some() || config.socket_path.is_some() {
+        shell_script.push_str(&generate_tmux_wrapper(
+            config.socket_name.as_deref(),
+            config.socket_path.as_ref().and_then(|path| path.to_str()),
+        ));
+        shell_script.push_str("\n\n");
+    }
+
     shell_script.push_str("### Create a new detached tmux session\n");
-    shell_script.push_str(&format!("tmux new-session -d -s \"$session_name\"\n\n\n"));
+    shell_script.push_str("# Its first window is removed once the saved windows are created\n");
+    shell_script.push_str(
+        "placeholder_window=$(tmux new-session -d -s \"$session_name\" -P -F '#{window_id}')\n\n",
+    );
+
+    if let Some(history_limit) = get_config().restore.history_limit {
+        shell_script.push_str("### Set the scrollback history limit\n");
+        shell_script.push_str(&format!(
+            "tmux set-option -t \"$session_name\" history-limit {}\n\n",
+            history_limit
+        ));
+    }
+
+    if !session.environment.is_empty() {
+        shell_script.push_str("### Set the session environment\n");
+        for (name, value) in &session.environment {
+            shell_script.push_str(&format!(
+                "tmux set-environment -t \"$session_name\" {} {}\n",
+                name,
+                shell_quote(value)
+            ));
+        }
+        shell_script.push('\n');
+    }
+    shell_script.push('\n');
 
+    // Windows and panes are targeted by the IDs tmux gives them when they are
+    // created, so the script doesn't depend on base-index and pane-base-index
     let mut active_pane = String::new();
     let mut active_window = String::new();
     let mut zoomed_panes: Vec<String> = vec![];
-    for window in &session.windows {
+    for (w, window) in session.windows.iter().enumerate() {
         shell_script.push_str(format!("## Window {}:{}\n", &window.id, &window.name).as_str());
-        let target_window = format!("\"$session_name\":{}", &window.id);
+        let target_window = format!("\"$pane_{}_0\"", w);
+        let pane_commands: Vec<Vec<String>> = window.panes.iter().map(get_pane_commands).collect();
         shell_script.push_str(&format!(
-            "tmux new-window -t {} -k -n {} -c {} \"{}\"\n\n",
-            &target_window, &window.name, &window.panes[0].cwd, &window.panes[0].commands[0]
+            "pane_{}_0=$(tmux new-window -t \"$session_name\": -n {} -c {}{} -P -F '#{{pane_id}}' {})\n\n",
+            w,
+            shell_word(&window.name),
+            template_cwd(&window.panes[0].cwd, &session.root),
+            get_environment_flags(&window.panes[0]),
+            double_quote(&pane_commands[0][0])
         ));
 
         let mut active_pane_current_window = String::new();
-        for (i, pane) in window.panes.iter().enumerate() {
-            if i != 0 {
-                // Create a new pane and run the first command in it
+        for (p, pane) in window.panes.iter().enumerate() {
+            let target_pane = format!("\"$pane_{}_{}\"", w, p);
+            if p != 0 {
+                // Create a new pane and run the first command in it. tmux puts
+                // the new pane right after the one it splits, so the previous
+                // pane is split for the panes to keep their order
                 shell_script.push_str(format!("# Create pane {}\n", &pane.id).as_str());
                 shell_script.push_str(&format!(
-                    "tmux split-window -t {} -c {} \"{}\"\n",
-                    &target_window, &pane.cwd, &pane.commands[0]
+                    "pane_{}_{}=$(tmux split-window -t \"$pane_{}_{}\" -c {}{} -P -F '#{{pane_id}}' {})\n",
+                    w,
+                    p,
+                    w,
+                    p - 1,
+                    template_cwd(&pane.cwd, &session.root),
+                    get_environment_flags(pane),
+                    double_quote(&pane_commands[p][0])
                 ));
             }
+            shell_script.push_str(&format!(
+                "tmux set-option -p -t {} {} {}\n\n",
+                &target_pane,
+                PANE_KEY_OPTION,
+                shell_word(&get_pane_key(&window.name, p))
+            ));
+
+            // Type the last command of an idle shell, without running it, as
+            // long as typing it doesn't press Enter
+            let last_command = pane
+                .history
+                .last()
+                .filter(|command| !command.contains('\n'));
+            if let (Some(command), [shell]) = (last_command, pane_commands[p].as_slice()) {
+                if is_shell_command(shell) {
+                    shell_script.push_str(
+                        format!("# Type the last command of pane {}\n", &pane.id).as_str(),
+                    );
+                    shell_script.push_str(&format!(
+                        "tmux send-keys -t {} -l {}\n\n",
+                        &target_pane,
+                        double_quote(command)
+                    ));
+                }
+            }
 
-            let target_pane = format!("\"$session_name\":{}.{}", &window.id, pane.id);
             if window.active && pane.active {
                 // Set the active pane to select it at the end
                 active_pane = format!("tmux select-pane -t {}\n\n", &target_pane);
@@ -50,12 +160,36 @@ pub fn generate_tmux_session_script(session: &TmuxSession) -> String {
                 zoomed_panes.push(format!("tmux resize-pane -t {} -Z\n", &target_pane));
             }
 
-            // Run the second command in the pane
-            for command in &pane.commands[1..] {
+            // Run the commands in the pane's shell, or only type the last one
+            // in prefilled panes, as the current config or the saved pane says
+            let prefill = pane.prefill || config.restore.is_pane_prefilled(&pane.commands);
+            let commands = &pane_commands[p][1..];
+            for (i, command) in commands.iter().enumerate() {
+                if prefill && i == commands.len() - 1 {
+                    shell_script.push_str(
+                        format!("# Type command in pane {}, without running it\n", &pane.id)
+                            .as_str(),
+                    );
+                    shell_script.push_str(&format!(
+                        "tmux set-option -p -t {} {} {}\n",
+                        &target_pane,
+                        PREFILL_OPTION,
+                        double_quote(command)
+                    ));
+                    shell_script.push_str(&format!(
+                        "deferred {} || tmux send-keys -t {} -l {}\n\n",
+                        shell_word(&get_pane_key(&window.name, p)),
+                        &target_pane,
+                        double_quote(command)
+                    ));
+                    continue;
+                }
                 shell_script.push_str(format!("# Run command in pane {}\n", &pane.id).as_str());
                 shell_script.push_str(&format!(
-                    "tmux send-keys -t {} \"{}\" C-m\n\n",
-                    &target_pane, command
+                    "deferred {} || tmux send-keys -t {} {} C-m\n\n",
+                    shell_word(&get_pane_key(&window.name, p)),
+                    &target_pane,
+                    double_quote(command)
                 ));
             }
         }
@@ -80,6 +214,10 @@ pub fn generate_tmux_session_script(session: &TmuxSession) -> String {
             .push_str(format!("## End of window {}:{}\n\n", &window.id, &window.name).as_str());
     }
 
+    shell_script.push_str("### Remove the placeholder window and renumber the windows\n");
+    shell_script.push_str("tmux kill-window -t \"$placeholder_window\"\n");
+    shell_script.push_str("tmux move-window -r -t \"$session_name\"\n\n");
+
     if !zoomed_panes.is_empty() {
         shell_script.push_str("### Zoom the zoomed panes\n");
         for zoomed_pane in zoomed_panes {
@@ -96,3 +234,68 @@ pub fn generate_tmux_session_script(session: &TmuxSession) -> String {
 
     shell_script
 }
+
+/// The commands of a pane, the first one being what the pane is started with.
+/// Panes are always started with a shell and their commands typed in it, so
+/// that a command that exits leaves the shell behind instead of closing the
+/// pane, and so that the commands of panes with a startup annotation can be
+/// deferred. The ssh command of remote panes reconnects to their remote
+/// directory.
+fn get_pane_commands(pane: &TmuxPane) -> Vec<String> {
+    let mut commands = pane.commands.clone();
+    if let (Some(remote), Some(command)) = (&pane.remote, commands.last_mut()) {
+        *command = format_ssh_command(remote);
+    }
+
+    if commands
+        .first()
+        .is_some_and(|command| is_shell_command(command))
+    {
+        return commands;
+    }
+    [vec![get_default_shell()], commands].concat()
+}
+
+/// The `-e` flags giving a pane the commands the shell hook puts back in its
+/// history, and the project directory when its commands use it.
+fn get_environment_flags(pane: &TmuxPane) -> String {
+    let mut variables = format_history_variables(&pane.history);
+    if pane
+        .commands
+        .iter()
+        .any(|command| command.contains("$PROJECT_ROOT"))
+    {
+        variables.push("PROJECT_ROOT=\"$PROJECT_ROOT\"".to_string());
+    }
+    variables
+        .iter()
+        .map(|variable| format!(" -e {}", variable))
+        .collect()
+}
+
+/// Shell function running tmux on the server given by the `TMUXESSION_SOCKET_*`
+/// variables, defaulting to the given socket when they are not set.
+pub fn generate_tmux_wrapper(socket_name: Option<&str>, socket_path: Option<&str>) -> String {
+    let mut wrapper = String::new();
+
+    wrapper.push_str("### Run tmux on the server the session is restored on\n");
+    wrapper.push_str(&format!(
+        "TMUXESSION_SOCKET_NAME=${{TMUXESSION_SOCKET_NAME-{}}}\n",
+        shell_quote(socket_name.unwrap_or_default())
+    ));
+    wrapper.push_str(&format!(
+        "TMUXESSION_SOCKET_PATH=${{TMUXESSION_SOCKET_PATH-{}}}\n",
+        shell_quote(socket_path.unwrap_or_default())
+    ));
+    wrapper.push_str("tmux() {\n");
+    wrapper.push_str("    if [ -n \"$TMUXESSION_SOCKET_PATH\" ]; then\n");
+    wrapper.push_str("        command tmux -S \"$TMUXESSION_SOCKET_PATH\" \"$@\"\n");
+    wrapper.push_str("    elif [ -n \"$TMUXESSION_SOCKET_NAME\" ]; then\n");
+    wrapper.push_str("        command tmux -L \"$TMUXESSION_SOCKET_NAME\" \"$@\"\n");
+    wrapper.push_str("    else\n");
+    wrapper.push_str("        command tmux \"$@\"\n");
+    wrapper.push_str("    fi\n");
+    wrapper.push_str("}\n");
+
+    wrapper
+}
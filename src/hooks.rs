@@ -0,0 +1,116 @@
+use crate::common::SessionHook;
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::process::Command;
+
+lazy_static! {
+    static ref HOOK_RE: Regex = Regex::new(r"(?m)^# @hook (.*)$").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum HookEvent {
+    /// Before the session is created, a failure aborts the restore
+    #[value(name = "on_restore_before")]
+    RestoreBefore,
+    /// Once the session is restored, before attaching to it
+    #[value(name = "on_restore_after")]
+    RestoreAfter,
+    /// Before the session script is written, a failure aborts the save
+    #[value(name = "on_save")]
+    Save,
+}
+
+pub fn parse_hooks(shell_script: &str) -> Result<Vec<SessionHook>, String> {
+    HOOK_RE
+        .captures_iter(shell_script)
+        .map(|caps| {
+            let annotation = caps[1].trim();
+            let (event, command) = annotation.split_once(' ').unwrap_or((annotation, ""));
+            let event = HookEvent::from_str(event, false)
+                .map_err(|_| format!("unknown hook `{}`", event))?;
+            if command.trim().is_empty() {
+                return Err(format!(
+                    "missing the command of the {} hook",
+                    event_name(event)
+                ));
+            }
+            Ok(SessionHook {
+                event,
+                command: command.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+pub fn format_hook(hook: &SessionHook) -> String {
+    format!("# @hook {} {}", event_name(hook.event), &hook.command)
+}
+
+fn event_name(event: HookEvent) -> String {
+    event.to_possible_value().unwrap().get_name().to_string()
+}
+
+/// Runs the hooks of `event` in order, stopping at the first one that fails.
+/// `env` is added to the environment of the hooks.
+pub fn run_hooks(
+    hooks: &[SessionHook],
+    event: HookEvent,
+    directory: &str,
+    env: &[(&str, &str)],
+) -> Result<(), String> {
+    for hook in hooks.iter().filter(|hook| hook.event == event) {
+        println!("Running {} hook: {}", event_name(event), &hook.command);
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&hook.command)
+            .current_dir(directory)
+            .envs(env.iter().copied())
+            .status()
+            .map_err(|err| format!("could not run `{}`: {}", &hook.command, err))?;
+        if !status.success() {
+            return Err(format!("`{}` failed with {}", &hook.command, status));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hooks() {
+        let shell_script = "# @hook on_restore_before ssh-add -l >/dev/null || ssh-add\n\
+            echo '# @hook on_save not at the start of a line'\n\
+            # @hook on_save  git stash list \n";
+
+        let hooks = parse_hooks(shell_script).unwrap();
+        assert_eq!(
+            hooks,
+            [
+                SessionHook {
+                    event: HookEvent::RestoreBefore,
+                    command: "ssh-add -l >/dev/null || ssh-add".to_string(),
+                },
+                SessionHook {
+                    event: HookEvent::Save,
+                    command: "git stash list".to_string(),
+                },
+            ]
+        );
+        assert_eq!(format_hook(&hooks[1]), "# @hook on_save git stash list");
+    }
+
+    #[test]
+    fn rejects_invalid_hooks() {
+        assert_eq!(
+            parse_hooks("# @hook on_attach tmux ls"),
+            Err("unknown hook `on_attach`".to_string())
+        );
+        assert_eq!(
+            parse_hooks("# @hook on_save"),
+            Err("missing the command of the on_save hook".to_string())
+        );
+    }
+}
@@ -0,0 +1,438 @@
+use crate::bundle::resolve_collisions;
+use crate::common::{
+    get_default_shell, get_session_script_path_for, TmuxPane, TmuxSession, TmuxWindow,
+};
+use crate::generate_script::generate_tmux_session_script;
+use crate::move_session::get_absolute_dir;
+use crate::save_session::save_session_script;
+use clap::ValueEnum;
+use serde_norway::Value;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ConfigFormat {
+    /// tmuxinator YAML project file
+    Tmuxinator,
+    /// tmuxp YAML or JSON workspace file
+    Tmuxp,
+}
+
+pub fn import_config(format: ConfigFormat, file: &Path) {
+    let content = fs::read_to_string(file).unwrap_or_else(|err| {
+        eprintln!("Error: Could not read {}: {}", file.display(), err);
+        std::process::exit(1);
+    });
+    // JSON is valid YAML, so this covers tmuxp's JSON files as well
+    let config: Value = serde_norway::from_str(&content).unwrap_or_else(|err| {
+        eprintln!("Error: Could not parse {}: {}", file.display(), err);
+        std::process::exit(1);
+    });
+
+    let session = match format {
+        ConfigFormat::Tmuxinator => convert_tmuxinator(&config),
+        ConfigFormat::Tmuxp => convert_tmuxp(&config),
+    };
+    if session.windows.is_empty() {
+        eprintln!("Error: {} doesn't define any windows", file.display());
+        std::process::exit(1);
+    }
+
+    let shell_script = generate_tmux_session_script(&session);
+    let Some((shell_script, profile)) = resolve_collisions(shell_script, &session.root, None)
+    else {
+        println!("Skipped importing {}", file.display());
+        return;
+    };
+
+    let file_path = get_session_script_path_for(&session.root, profile.as_deref());
+    save_session_script(&file_path, &shell_script);
+}
+
+pub fn convert_tmuxinator(config: &Value) -> TmuxSession {
+    let root = expand_dir(get_str(config, "root"), &get_absolute_dir("."));
+    let mut pre_window = get_commands(&config["pre_window"]);
+    if pre_window.is_empty() {
+        // Older name of the same option
+        pre_window = get_commands(&config["pre_tab"]);
+    }
+
+    let windows = get_sequence(&config["windows"])
+        .iter()
+        .enumerate()
+        .filter_map(|(i, window)| {
+            // Every window is a single entry mapping its name to its definition
+            let (name, definition) = window.as_mapping()?.iter().next()?;
+            let name = value_to_string(name);
+
+            let (cwd, layout, pre, panes) = match definition {
+                Value::Mapping(_) => (
+                    expand_dir(get_str(definition, "root"), &root),
+                    get_str(definition, "layout").map(str::to_string),
+                    get_commands(&definition["pre"]),
+                    get_sequence(&definition["panes"])
+                        .iter()
+                        .map(|pane| match pane {
+                            // Named panes map their name to their commands
+                            Value::Mapping(mapping) => mapping
+                                .values()
+                                .next()
+                                .map(get_commands)
+                                .unwrap_or_default(),
+                            pane => get_commands(pane),
+                        })
+                        .collect(),
+                ),
+                commands => (root.clone(), None, vec![], vec![get_commands(commands)]),
+            };
+
+            let panes = if panes.is_empty() {
+                vec![vec![]]
+            } else {
+                panes
+            };
+            let panes = panes
+                .into_iter()
+                .enumerate()
+                .map(|(j, commands)| {
+                    let commands = [pre_window.clone(), pre.clone(), commands].concat();
+                    create_pane(j, &cwd, j == 0, commands)
+                })
+                .collect();
+
+            Some(create_window(i, name, layout, i == 0, panes))
+        })
+        .collect();
+
+    TmuxSession {
+        name: get_str(config, "name").unwrap_or("tmuxinator").to_string(),
+        root,
+        environment: vec![],
+        windows,
+        startup: vec![],
+        hooks: vec![],
+    }
+}
+
+pub fn convert_tmuxp(config: &Value) -> TmuxSession {
+    let root = expand_dir(get_str(config, "start_directory"), &get_absolute_dir("."));
+    let session_before = get_commands(&config["shell_command_before"]);
+    // The scripts only set the session environment, so the window and pane
+    // environments are added to it
+    let mut environment = get_environment(&config["environment"]);
+
+    let windows_config = get_sequence(&config["windows"]);
+    let has_focused_window = windows_config.iter().any(is_focused);
+
+    let windows = windows_config
+        .iter()
+        .enumerate()
+        .map(|(i, window)| {
+            let cwd = expand_dir(get_str(window, "start_directory"), &root);
+            let window_before = get_commands(&window["shell_command_before"]);
+            add_environment(&mut environment, &window["environment"]);
+
+            let panes_config = get_sequence(&window["panes"]);
+            let panes_config = if panes_config.is_empty() {
+                vec![Value::Null]
+            } else {
+                panes_config
+            };
+            let has_focused_pane = panes_config.iter().any(is_focused);
+
+            let panes = panes_config
+                .iter()
+                .enumerate()
+                .map(|(j, pane)| {
+                    let (pane_cwd, commands) = match pane {
+                        Value::Mapping(_) => {
+                            add_environment(&mut environment, &pane["environment"]);
+                            (
+                                expand_dir(get_str(pane, "start_directory"), &cwd),
+                                get_commands(&pane["shell_command"]),
+                            )
+                        }
+                        // Shorthands of an empty pane
+                        Value::String(shorthand)
+                            if ["blank", "pane", "null"].contains(&shorthand.as_str()) =>
+                        {
+                            (cwd.clone(), vec![])
+                        }
+                        commands => (cwd.clone(), get_commands(commands)),
+                    };
+                    let commands =
+                        [session_before.clone(), window_before.clone(), commands].concat();
+                    let active = if has_focused_pane {
+                        is_focused(pane)
+                    } else {
+                        j == 0
+                    };
+                    create_pane(j, &pane_cwd, active, commands)
+                })
+                .collect();
+
+            let name = get_str(window, "window_name").map_or(i.to_string(), str::to_string);
+            let layout = get_str(window, "layout").map(str::to_string);
+            let active = if has_focused_window {
+                is_focused(window)
+            } else {
+                i == 0
+            };
+            create_window(i, name, layout, active, panes)
+        })
+        .collect();
+
+    TmuxSession {
+        name: get_str(config, "session_name")
+            .unwrap_or("tmuxp")
+            .to_string(),
+        root,
+        environment,
+        windows,
+        startup: vec![],
+        hooks: vec![],
+    }
+}
+
+fn create_window(
+    index: usize,
+    name: String,
+    layout: Option<String>,
+    active: bool,
+    panes: Vec<TmuxPane>,
+) -> TmuxWindow {
+    TmuxWindow {
+        id: index.to_string(),
+        name,
+        layout: layout.unwrap_or("tiled".to_string()),
+        active,
+        zoomed: false,
+        panes,
+    }
+}
+
+fn create_pane(index: usize, cwd: &str, active: bool, commands: Vec<String>) -> TmuxPane {
+    // Panes start with the user's shell, the commands are then typed in it
+    TmuxPane {
+        id: index.to_string(),
+        cwd: cwd.to_string(),
+        active,
+        commands: [vec![get_default_shell()], commands].concat(),
+        remote: None,
+        history: vec![],
+        prefill: false,
+    }
+}
+
+fn is_focused(value: &Value) -> bool {
+    match &value["focus"] {
+        Value::Bool(focus) => *focus,
+        Value::String(focus) => focus == "true",
+        _ => false,
+    }
+}
+
+fn get_environment(value: &Value) -> Vec<(String, String)> {
+    let mut environment = vec![];
+    add_environment(&mut environment, value);
+    environment
+}
+
+/// Adds the variables of an `environment` mapping, replacing the earlier
+/// values of the same variables.
+fn add_environment(environment: &mut Vec<(String, String)>, value: &Value) {
+    let Some(mapping) = value.as_mapping() else {
+        return;
+    };
+    for (name, value) in mapping {
+        let name = value_to_string(name);
+        let value = value_to_string(value);
+        match environment.iter_mut().find(|(other, _)| *other == name) {
+            Some(variable) => variable.1 = value,
+            None => environment.push((name, value)),
+        }
+    }
+}
+
+fn get_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+fn get_sequence(value: &Value) -> Vec<Value> {
+    value.as_sequence().cloned().unwrap_or_default()
+}
+
+/// Commands can be given as a single string or a list of strings, tmuxp also
+/// allows `{ cmd: ... }` entries.
+fn get_commands(value: &Value) -> Vec<String> {
+    match value {
+        Value::Sequence(commands) => commands.iter().flat_map(get_commands).collect(),
+        Value::Mapping(_) => get_commands(&value["cmd"]),
+        Value::Null => vec![],
+        command => {
+            let command = value_to_string(command);
+            if command.trim().is_empty() {
+                vec![]
+            } else {
+                vec![command]
+            }
+        }
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Number(value) => value.to_string(),
+        Value::Bool(value) => value.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Expands `~` and makes relative directories relative to `parent`.
+fn expand_dir(dir: Option<&str>, parent: &str) -> String {
+    let Some(dir) = dir else {
+        return parent.to_string();
+    };
+
+    let home = env::var("HOME").unwrap_or_default();
+    let dir = match dir.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", home, rest),
+        _ => dir.to_string(),
+    };
+    let dir = Path::new(parent).join(dir).to_string_lossy().to_string();
+    get_absolute_dir(&dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The commands typed in each pane of each window, without the shell.
+    fn get_typed_commands(session: &TmuxSession) -> Vec<Vec<Vec<String>>> {
+        session
+            .windows
+            .iter()
+            .map(|window| {
+                window
+                    .panes
+                    .iter()
+                    .map(|pane| pane.commands[1..].to_vec())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn parse(content: &str) -> Value {
+        serde_norway::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn converts_tmuxinator_projects() {
+        let config = parse(
+            "
+name: web
+root: /srv/web
+pre_window: nvm use
+windows:
+  - editor: vim
+  - servers:
+      root: api
+      layout: main-vertical
+      pre: source .env
+      panes:
+        - npm run dev
+        - logs:
+            - cd logs
+            - tail -f app.log
+  - shell:
+",
+        );
+        let session = convert_tmuxinator(&config);
+
+        assert_eq!(session.name, "web");
+        assert_eq!(session.root, "/srv/web");
+        assert_eq!(
+            get_typed_commands(&session),
+            vec![
+                vec![vec!["nvm use", "vim"]],
+                vec![
+                    vec!["nvm use", "source .env", "npm run dev"],
+                    vec!["nvm use", "source .env", "cd logs", "tail -f app.log"],
+                ],
+                vec![vec!["nvm use"]],
+            ]
+        );
+        let servers = &session.windows[1];
+        assert_eq!(servers.name, "servers");
+        assert_eq!(servers.layout, "main-vertical");
+        assert_eq!(servers.panes[1].cwd, "/srv/web/api");
+        assert_eq!(session.windows[0].layout, "tiled");
+        assert!(session.windows[0].active && !servers.active);
+    }
+
+    #[test]
+    fn converts_tmuxp_workspaces() {
+        let config = parse(
+            "
+session_name: web
+start_directory: /srv/web
+shell_command_before: nvm use
+environment:
+  NODE_ENV: development
+windows:
+  - window_name: editor
+    panes:
+      - vim
+  - window_name: servers
+    start_directory: api
+    focus: true
+    environment:
+      PORT: 8080
+    panes:
+      - shell_command:
+          - cmd: npm run dev
+        environment:
+          NODE_ENV: test
+      - blank
+      - pane
+      - null
+      - 'null'
+      - start_directory: /var/log
+        focus: true
+",
+        );
+        let session = convert_tmuxp(&config);
+
+        assert_eq!(session.name, "web");
+        assert_eq!(session.root, "/srv/web");
+        assert_eq!(
+            session.environment,
+            vec![
+                ("NODE_ENV".to_string(), "test".to_string()),
+                ("PORT".to_string(), "8080".to_string()),
+            ]
+        );
+        assert_eq!(
+            get_typed_commands(&session),
+            vec![
+                vec![vec!["nvm use", "vim"]],
+                vec![
+                    vec!["nvm use", "npm run dev"],
+                    vec!["nvm use"],
+                    vec!["nvm use"],
+                    vec!["nvm use"],
+                    vec!["nvm use"],
+                    vec!["nvm use"],
+                ],
+            ]
+        );
+        let servers = &session.windows[1];
+        assert!(servers.active && !session.windows[0].active);
+        assert_eq!(servers.panes[1].cwd, "/srv/web/api");
+        assert_eq!(servers.panes[5].cwd, "/var/log");
+        let active_panes: Vec<bool> = servers.panes.iter().map(|pane| pane.active).collect();
+        assert_eq!(active_panes, [false, false, false, false, false, true]);
+    }
+}
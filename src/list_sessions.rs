@@ -1,15 +1,30 @@
-use crate::common::{capture_session_name_from_script, get_data_dir, UserOption};
+use crate::common::{
+    capture_session_name_from_script, get_data_dir, is_session_stale, parse_script_file_name,
+    SavedSession, UserOption,
+};
+use crate::move_session::get_absolute_dir;
 use crate::restore_session::restore_tmux_session;
+use crate::state::get_last_used;
 use crate::ui::get_user_option;
+use clap::ValueEnum;
 use std::fs;
-use urlencoding::decode;
+use std::path::Path;
 
-pub fn list_sessions() {
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SortOrder {
+    /// Most recently saved or restored first
+    Recent,
+    /// Alphabetically by session name
+    Name,
+    /// Alphabetically by directory
+    Directory,
+}
+
+pub fn get_saved_sessions() -> Vec<SavedSession> {
     let session_dir = get_data_dir().to_path_buf();
     let entries = fs::read_dir(&session_dir).expect("Could not read tmuxession data directory");
 
-    let mut session_files = Vec::new();
-    let mut session_names = Vec::new();
+    let mut sessions = Vec::new();
     for entry in entries {
         let entry = entry.expect("Could not read tmuxession data directory entry");
         let path = entry.path();
@@ -24,19 +39,109 @@ pub fn list_sessions() {
                     };
 
                     // Read the session_name value
-                    let session_name = capture_session_name_from_script(&shell_script);
-                    if session_name.is_empty() {
+                    let name = capture_session_name_from_script(&shell_script);
+                    if name.is_empty() {
                         continue;
                     }
 
-                    session_names.push(session_name);
-                    session_files.push(path);
+                    let file_name = path.file_name().unwrap().to_str().unwrap();
+                    let Some((directory, profile)) = parse_script_file_name(file_name) else {
+                        continue;
+                    };
+
+                    sessions.push(SavedSession {
+                        path,
+                        name,
+                        directory,
+                        profile,
+                    });
                 }
             }
         }
     }
 
-    if session_files.is_empty() {
+    sessions
+}
+
+/// Saved sessions matching `query`, either by session name or by directory.
+pub fn find_saved_sessions(query: &str) -> Vec<SavedSession> {
+    let directory = get_absolute_dir(query);
+    get_saved_sessions()
+        .into_iter()
+        .filter(|session| session.name == query || session.directory == directory)
+        .collect()
+}
+
+pub fn sort_sessions(sessions: &mut [SavedSession], order: SortOrder) {
+    match order {
+        SortOrder::Recent => {
+            let last_used = get_last_used();
+            // Fall back to the modification time of scripts never restored
+            let get_timestamp = |session: &SavedSession| {
+                fs::canonicalize(&session.path)
+                    .ok()
+                    .and_then(|path| last_used.get(&path).copied())
+                    .unwrap_or_else(|| get_modified_timestamp(&session.path))
+            };
+            sessions.sort_by_cached_key(|session| std::cmp::Reverse(get_timestamp(session)));
+        }
+        SortOrder::Name => {
+            sessions.sort_by_cached_key(|session| session.name.to_lowercase());
+        }
+        SortOrder::Directory => {
+            sessions.sort_by(|a, b| a.directory.cmp(&b.directory));
+        }
+    }
+}
+
+fn get_modified_timestamp(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn get_parent_directory(session: &SavedSession) -> String {
+    Path::new(&session.directory)
+        .parent()
+        .map_or(String::new(), |parent| parent.to_string_lossy().to_string())
+}
+
+fn group_sessions_by(sessions: &mut [SavedSession], get_key: impl Fn(&SavedSession) -> String) {
+    // Keep the sessions with the same key together, ordering the groups by
+    // their first session in the current order
+    let mut keys: Vec<String> = Vec::new();
+    for session in sessions.iter() {
+        let key = get_key(session);
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    sessions.sort_by_key(|session| {
+        let key = get_key(session);
+        keys.iter().position(|k| *k == key).unwrap()
+    });
+}
+
+pub fn get_session_label(session: &SavedSession) -> String {
+    match &session.profile {
+        Some(profile) => format!("{}: {} [{}]", &session.name, &session.directory, profile),
+        None => format!("{}: {}", &session.name, &session.directory),
+    }
+}
+
+pub fn list_sessions(order: SortOrder, group: bool) {
+    let mut sessions = get_saved_sessions();
+    sort_sessions(&mut sessions, order);
+
+    // The profiles of a directory are always listed together
+    group_sessions_by(&mut sessions, |session| session.directory.clone());
+    if group {
+        group_sessions_by(&mut sessions, get_parent_directory);
+    }
+
+    if sessions.is_empty() {
         println!("No saved tmuxession sessions found.");
         println!("Try running `tmuxession save` inside a session to save it first.");
         return;
@@ -44,21 +149,37 @@ pub fn list_sessions() {
 
     let mut options: Vec<UserOption> = Vec::new();
 
-    for (i, path) in session_files.iter().enumerate() {
-        let encoded_name = path.file_stem().unwrap().to_str().unwrap();
-        let decoded_name = decode(encoded_name).unwrap();
+    let mut current_parent = None;
+    for (i, session) in sessions.iter().enumerate() {
+        let mut label = format!("[{}] {}", i + 1, get_session_label(session));
+        if is_session_stale(session) {
+            label.push_str(" (stale)");
+        }
 
-        let label = format!("[{}] {}: {}", i + 1, &session_names[i], decoded_name);
+        if group {
+            let parent = get_parent_directory(session);
+            if current_parent.as_ref() != Some(&parent) {
+                options.push(UserOption {
+                    label: format!("{}/", &parent),
+                    keybind: ' ',
+                    header: true,
+                });
+                current_parent = Some(parent);
+            }
+            label = format!("  {}", label);
+        }
 
         options.push(UserOption {
             label: label.to_string(),
-            keybind: (i as u8 + '1' as u8) as char,
+            keybind: (i as u8 + b'1') as char,
+            header: false,
         });
     }
 
     options.push(UserOption {
         label: "[q] Quit".to_string(),
         keybind: 'q',
+        header: false,
     });
 
     let choice = get_user_option("Select a session to restore:", options);
@@ -66,8 +187,90 @@ pub fn list_sessions() {
     if choice == 'q' {
         return;
     }
-    let index = (choice as u8).checked_sub('1' as u8).unwrap();
-    let selected_path = session_files.get(index as usize).unwrap();
-    let script_path = selected_path.to_str().unwrap().to_string();
-    restore_tmux_session(Some(script_path));
+    let index = (choice as u8).checked_sub(b'1').unwrap();
+    let selected_session = sessions.get(index as usize).unwrap();
+    let script_path = selected_session.path.to_str().unwrap().to_string();
+    restore_tmux_session(Some(script_path), None, false);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn saved_session(name: &str, directory: &str, profile: Option<&str>) -> SavedSession {
+        SavedSession {
+            path: PathBuf::from(format!("{}.sh", name)),
+            name: name.to_string(),
+            directory: directory.to_string(),
+            profile: profile.map(str::to_string),
+        }
+    }
+
+    fn get_labels(sessions: &[SavedSession]) -> Vec<String> {
+        sessions.iter().map(get_session_label).collect()
+    }
+
+    #[test]
+    fn sorts_by_name_ignoring_case() {
+        let mut sessions = vec![
+            saved_session("web", "/srv/web", None),
+            saved_session("API", "/srv/api", None),
+            saved_session("docs", "/home/me/docs", None),
+        ];
+        sort_sessions(&mut sessions, SortOrder::Name);
+        assert_eq!(
+            get_labels(&sessions),
+            ["API: /srv/api", "docs: /home/me/docs", "web: /srv/web"]
+        );
+    }
+
+    #[test]
+    fn sorts_by_directory() {
+        let mut sessions = vec![
+            saved_session("web", "/srv/web", None),
+            saved_session("api", "/srv/api", None),
+            saved_session("docs", "/home/me/docs", None),
+        ];
+        sort_sessions(&mut sessions, SortOrder::Directory);
+        assert_eq!(
+            get_labels(&sessions),
+            ["docs: /home/me/docs", "api: /srv/api", "web: /srv/web"]
+        );
+    }
+
+    #[test]
+    fn groups_keep_the_order_of_their_first_session() {
+        let mut sessions = vec![
+            saved_session("web", "/srv/web", None),
+            saved_session("docs", "/home/me/docs", None),
+            saved_session("web", "/srv/web", Some("debug")),
+            saved_session("api", "/srv/api", None),
+            saved_session("notes", "/home/me/notes", None),
+        ];
+
+        group_sessions_by(&mut sessions, |session| session.directory.clone());
+        assert_eq!(
+            get_labels(&sessions),
+            [
+                "web: /srv/web",
+                "web: /srv/web [debug]",
+                "docs: /home/me/docs",
+                "api: /srv/api",
+                "notes: /home/me/notes",
+            ]
+        );
+
+        group_sessions_by(&mut sessions, get_parent_directory);
+        assert_eq!(
+            get_labels(&sessions),
+            [
+                "web: /srv/web",
+                "web: /srv/web [debug]",
+                "api: /srv/api",
+                "docs: /home/me/docs",
+                "notes: /home/me/notes",
+            ]
+        );
+    }
 }
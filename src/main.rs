@@ -1,19 +1,53 @@
+mod bundle;
 mod common;
+mod completions;
+mod config;
 mod edit_session_script;
+mod editor_session;
+mod export_config;
+mod gc;
 mod generate_script;
+mod hooks;
+mod import_config;
 mod list_sessions;
+mod menu;
+mod move_session;
+mod parse_script;
+mod remote_pane;
 mod restore_session;
 mod save_session;
+mod session_file;
+mod shell_history;
+mod startup;
+mod state;
+mod stop_session;
+#[cfg(test)]
+mod test_fixtures;
 mod tmux_commands;
 mod ui;
 
-use crate::common::is_inside_tmux;
-use crate::list_sessions::list_sessions;
-use clap::{Parser, Subcommand};
+use crate::bundle::{export_bundle, import_bundle};
+use crate::common::{is_inside_tmux, Shell};
+use crate::config::{init_config, print_config};
+use crate::list_sessions::{list_sessions, SortOrder};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::{ArgValueCandidates, CompleteEnv};
+use completions::{
+    complete_directories, complete_profiles, complete_scripts, complete_sessions,
+    get_completed_data_dir, print_completions, print_man_page,
+};
 use edit_session_script::edit_session_script;
+use export_config::export_config;
+use gc::collect_garbage;
+use import_config::{import_config, ConfigFormat};
+use menu::show_menu;
+use move_session::move_session;
 use restore_session::restore_tmux_session;
 use save_session::save_tmux_session;
+use shell_history::print_shell_hook;
 use std::env;
+use std::path::PathBuf;
+use stop_session::stop_session;
 
 /// tmuxession: Save and restore tmux sessions.
 #[derive(Debug, Parser)]
@@ -26,6 +60,16 @@ use std::env;
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Name of the tmux server socket, like `tmux -L`
+    #[arg(short = 'L', long, global = true, env = "TMUXESSION_SOCKET_NAME")]
+    socket_name: Option<String>,
+    /// Path of the tmux server socket, like `tmux -S`.
+    /// Takes precedence over the socket name
+    #[arg(short = 'S', long, global = true, env = "TMUXESSION_SOCKET_PATH")]
+    socket_path: Option<PathBuf>,
+    /// Directory where the session scripts are saved
+    #[arg(long, global = true, env = "TMUXESSION_DATA_DIR")]
+    data_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -40,48 +84,241 @@ enum Commands {
         /// If not provided, the current session name will be used.
         #[arg(long)]
         name: Option<String>,
+        /// Save the session script inside the current directory
+        /// (as .tmuxession.sh) instead of the data directory,
+        /// so that it can be committed with the project
+        #[arg(long, conflicts_with = "script")]
+        local: bool,
+        /// Save the session under a named profile, so that a directory
+        /// can have several different sessions
+        #[arg(long, conflicts_with = "script", add = ArgValueCandidates::new(complete_profiles))]
+        profile: Option<String>,
     },
     /// Restore the TMUX session
     #[command(visible_alias = "r")]
     Restore {
         /// Optional script parameter
-        #[arg(long)]
+        #[arg(long, add = ArgValueCandidates::new(complete_scripts))]
         script: Option<String>,
+        /// Restore the session saved under this profile
+        #[arg(long, conflicts_with = "script", add = ArgValueCandidates::new(complete_profiles))]
+        profile: Option<String>,
+        /// Restore the script even if its checksum doesn't match its content
+        #[arg(long)]
+        force: bool,
+    },
+    /// Save a running TMUX session, close its panes gracefully and kill it.
+    /// Stops the current session when no session is given
+    Stop {
+        /// The name of the running session to stop
+        session: Option<String>,
     },
     /// Edit the saved TMUX session for the current directory.
     /// This command will open the saved script if it exists
     /// in $EDITOR or vi
     #[command(visible_alias = "e")]
-    Edit {},
+    Edit {
+        /// Edit the session saved under this profile
+        #[arg(long, add = ArgValueCandidates::new(complete_profiles))]
+        profile: Option<String>,
+    },
     /// List all saved TMUX sessions and allows to pick one to restore [default]
     #[command(visible_aliases = ["ls", "l"])]
-    List {},
+    List {
+        /// Order in which the sessions are listed
+        #[arg(long, value_enum, default_value_t = SortOrder::Recent)]
+        sort: SortOrder,
+        /// Group the sessions by their parent directory
+        #[arg(long)]
+        group: bool,
+    },
+    /// Find saved TMUX sessions whose directory no longer exists
+    /// and delete or archive them
+    Gc {},
+    /// Move the saved TMUX session of a directory that has been moved,
+    /// together with the cwds of its panes
+    Mv {
+        /// The directory the session was saved for
+        #[arg(add = ArgValueCandidates::new(complete_directories))]
+        old_dir: String,
+        /// The new location of the directory
+        new_dir: String,
+        /// Only show what would be changed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export saved TMUX sessions to a bundle that can be imported
+    /// on another machine, or a session to a tmuxinator/tmuxp project file
+    Export {
+        /// The session to export, by session name or directory
+        #[arg(required_unless_present = "all", add = ArgValueCandidates::new(complete_sessions))]
+        session: Option<String>,
+        /// Export all the saved sessions
+        #[arg(long, conflicts_with = "session")]
+        all: bool,
+        /// Export the session as a project file of another tool instead
+        /// of a bundle
+        #[arg(long, value_enum, conflicts_with = "all")]
+        to: Option<ConfigFormat>,
+        /// Path of the file to create, defaults to tmuxession.tar.gz for
+        /// bundles and <session name>.yml for project files
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Import the TMUX sessions of a bundle created by `export`,
+    /// or a tmuxinator/tmuxp project file
+    Import {
+        /// Path of the bundle or project file
+        file: PathBuf,
+        /// Import a project file of another tool instead of a bundle
+        #[arg(long, value_enum)]
+        from: Option<ConfigFormat>,
+        /// Move the sessions under the home directory of the exporting
+        /// user to the current home directory
+        #[arg(long, conflicts_with = "from")]
+        remap_home: bool,
+        /// Import the scripts whose checksum doesn't match their content
+        #[arg(long, conflicts_with = "from")]
+        force: bool,
+    },
+    /// Print the effective configuration
+    Config {},
+    /// Print the shell completions, completing the saved sessions too.
+    /// Load them with e.g. `source <(tmuxession completions bash)`
+    Completions {
+        /// The shell to print the completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page
+    Man {},
+    /// Print the shell hook recording the last commands run in each pane,
+    /// which are saved with the session and put back in the shell history
+    /// on restore. Load it with e.g. `source <(tmuxession shell-hook bash)`
+    ShellHook {
+        /// The shell to print the hook for
+        #[arg(value_enum)]
+        shell: Shell,
+        /// How many commands are recorded for each pane
+        #[arg(long, default_value_t = 10)]
+        size: usize,
+    },
+    /// Show the saved TMUX sessions in a native tmux menu.
+    /// Meant to be bound to a key, must be run inside tmux
+    #[command(visible_alias = "m")]
+    Menu {
+        /// Run the session picker inside a tmux popup instead
+        #[arg(long)]
+        popup: bool,
+    },
 }
 
 fn main() {
+    // Restored sessions inherit the socket variables `tmuxession restore`
+    // sets, the one that wasn't given being empty
+    for name in ["TMUXESSION_SOCKET_NAME", "TMUXESSION_SOCKET_PATH"] {
+        if env::var_os(name).is_some_and(|value| value.is_empty()) {
+            env::remove_var(name);
+        }
+    }
+
+    // Answers the shell when it asks for completions, the completers reading
+    // the sessions of the data directory given in the completed command line
+    if env::var("COMPLETE").is_ok_and(|shell| !shell.is_empty() && shell != "0") {
+        init_config(get_completed_data_dir(), None, None);
+    }
+    CompleteEnv::with_factory(Cli::command).complete();
+
     let cli = Cli::parse();
+    init_config(
+        cli.data_dir.clone(),
+        cli.socket_name.clone(),
+        cli.socket_path.clone(),
+    );
 
     match &cli.command {
         Some(command) => match command {
-            Commands::Save { script, name } => {
+            Commands::Save {
+                script,
+                name,
+                local,
+                profile,
+            } => {
                 if !is_inside_tmux() {
                     eprintln!("Error: `tmuxession save` must be run inside a tmux session");
                     std::process::exit(1);
                 }
-                save_tmux_session(script.clone(), name.clone());
+                save_tmux_session(script.clone(), name.clone(), *local, profile.clone());
+            }
+            Commands::Restore {
+                script,
+                profile,
+                force,
+            } => {
+                restore_tmux_session(script.clone(), profile.clone(), *force);
             }
-            Commands::Restore { script } => {
-                restore_tmux_session(script.clone());
+            Commands::Stop { session } => {
+                stop_session(session.clone());
             }
-            Commands::Edit {} => {
-                edit_session_script();
+            Commands::Edit { profile } => {
+                edit_session_script(profile.clone());
             }
-            Commands::List {} => {
-                list_sessions();
+            Commands::List { sort, group } => {
+                list_sessions(*sort, *group);
+            }
+            Commands::Gc {} => {
+                collect_garbage();
+            }
+            Commands::Mv {
+                old_dir,
+                new_dir,
+                dry_run,
+            } => {
+                move_session(old_dir, new_dir, *dry_run);
+            }
+            Commands::Export {
+                session,
+                all,
+                to,
+                output,
+            } => match (to, session) {
+                (Some(format), Some(session)) => export_config(*format, session, output.clone()),
+                _ => {
+                    let output = output.clone().unwrap_or("tmuxession.tar.gz".into());
+                    export_bundle(session.clone(), *all, &output);
+                }
+            },
+            Commands::Import {
+                file,
+                from,
+                remap_home,
+                force,
+            } => match from {
+                Some(format) => import_config(*format, file),
+                None => import_bundle(file, *remap_home, *force),
+            },
+            Commands::Config {} => {
+                print_config();
+            }
+            Commands::Completions { shell } => {
+                print_completions(*shell);
+            }
+            Commands::Man {} => {
+                print_man_page(Cli::command());
+            }
+            Commands::ShellHook { shell, size } => {
+                print_shell_hook(*shell, *size);
+            }
+            Commands::Menu { popup } => {
+                if !is_inside_tmux() {
+                    eprintln!("Error: `tmuxession menu` must be run inside a tmux session");
+                    std::process::exit(1);
+                }
+                show_menu(*popup);
             }
         },
         None => {
-            list_sessions();
+            list_sessions(SortOrder::Recent, false);
         }
     }
 }
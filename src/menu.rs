@@ -0,0 +1,134 @@
+use crate::common::{shell_quote, SavedSession};
+use crate::config::get_config;
+use crate::list_sessions::{get_saved_sessions, get_session_label, sort_sessions, SortOrder};
+use crate::tmux_commands::{display_menu, display_popup};
+use std::env;
+
+const MENU_KEYS: &str = "123456789abcdefghilmnoprstuvwxyz";
+
+pub fn show_menu(popup: bool) {
+    let executable = get_executable();
+
+    if popup {
+        // Run the regular picker inside a popup over the current pane
+        display_popup(&format!("{} list", executable));
+        return;
+    }
+
+    let mut sessions = get_saved_sessions();
+    sort_sessions(&mut sessions, SortOrder::Recent);
+    if sessions.is_empty() {
+        display_popup("echo 'No saved tmuxession sessions found.'; read -r _");
+        return;
+    }
+
+    let items = get_menu_items(&sessions, &executable);
+    display_menu("#[align=centre] tmuxession ", &items);
+}
+
+/// The label, key and command of the menu item of each session. Only the
+/// first sessions get a key.
+fn get_menu_items(sessions: &[SavedSession], executable: &str) -> Vec<(String, String, String)> {
+    sessions
+        .iter()
+        .enumerate()
+        .map(|(i, session)| {
+            let label = get_session_label(session);
+            let key = MENU_KEYS
+                .chars()
+                .nth(i)
+                .map_or(String::new(), |c| c.to_string());
+            // Restore inside a popup so that the prompts still have a terminal
+            let restore_command = format!(
+                "{} restore --script {}",
+                executable,
+                shell_quote(&session.path.to_string_lossy())
+            );
+            let command = format!("display-popup -E {}", shell_quote(&restore_command));
+
+            (escape_format(&label), key, escape_format(&command))
+        })
+        .collect()
+}
+
+fn get_executable() -> String {
+    let executable = env::current_exe()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or("tmuxession".to_string());
+    let mut executable = shell_quote(&executable);
+
+    // The popups don't inherit our environment, so pass the global options along
+    let config = get_config();
+    if let Some(socket_path) = &config.socket_path {
+        executable.push_str(&format!(
+            " -S {}",
+            shell_quote(&socket_path.to_string_lossy())
+        ));
+    } else if let Some(socket_name) = &config.socket_name {
+        executable.push_str(&format!(" -L {}", shell_quote(socket_name)));
+    }
+    if let Some(data_dir) = &config.data_dir {
+        executable.push_str(&format!(
+            " --data-dir {}",
+            shell_quote(&data_dir.to_string_lossy())
+        ));
+    }
+
+    executable
+}
+
+fn escape_format(value: &str) -> String {
+    // Menu labels and commands are expanded as tmux formats
+    value.replace('#', "##")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn saved_session(name: &str, directory: &str) -> SavedSession {
+        SavedSession {
+            path: PathBuf::from(format!("/data/{}.sh", name)),
+            name: name.to_string(),
+            directory: directory.to_string(),
+            profile: None,
+        }
+    }
+
+    #[test]
+    fn builds_the_restore_item_of_each_session() {
+        let sessions = [saved_session("web#2", "/srv/it's")];
+        let items = get_menu_items(&sessions, "'/usr/bin/tmuxession' -L work");
+        assert_eq!(
+            items,
+            [(
+                "web##2: /srv/it's".to_string(),
+                "1".to_string(),
+                "display-popup -E ''\\''/usr/bin/tmuxession'\\'' -L work restore --script \
+                 '\\''/data/web##2.sh'\\'''"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn gives_keys_to_the_first_sessions_only() {
+        let sessions: Vec<_> = (0..MENU_KEYS.len() + 2)
+            .map(|i| saved_session(&format!("s{}", i), "/srv"))
+            .collect();
+        let keys: Vec<String> = get_menu_items(&sessions, "tmuxession")
+            .into_iter()
+            .map(|(_, key, _)| key)
+            .collect();
+
+        assert_eq!(keys[..3], ["1", "2", "3"]);
+        assert_eq!(keys[9..11], ["a", "b"]);
+        assert_eq!(keys[MENU_KEYS.len() - 1], "z");
+        assert_eq!(keys[MENU_KEYS.len()..], ["", ""]);
+        // Keys that tmux menus use themselves are skipped
+        assert!(!keys
+            .iter()
+            .any(|key| key == "j" || key == "k" || key == "q"));
+    }
+}
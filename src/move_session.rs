@@ -0,0 +1,211 @@
+use crate::common::{
+    expand_cwd, get_session_files_dir, get_session_script_path_for, template_cwd, TMUX_PANE_CWD_RE,
+};
+use crate::editor_session::relocate_editor_sessions;
+use crate::list_sessions::get_saved_sessions;
+use crate::session_file::write_session_script;
+use std::env;
+use std::fs;
+use std::path::{Component, PathBuf};
+
+pub fn move_session(old_dir: &str, new_dir: &str, dry_run: bool) {
+    let old_dir = get_absolute_dir(old_dir);
+    let new_dir = get_absolute_dir(new_dir);
+
+    // Sessions saved for subdirectories of the moved directory move along
+    let sessions: Vec<_> = get_saved_sessions()
+        .into_iter()
+        .filter_map(|session| {
+            let directory = relocate_path(&session.directory, &old_dir, &new_dir)?;
+            Some((session, directory))
+        })
+        .collect();
+
+    if sessions.is_empty() {
+        eprintln!(
+            "No saved session found for {} or any of its subdirectories",
+            &old_dir
+        );
+        std::process::exit(1);
+    }
+
+    for (session, directory) in sessions {
+        let file_path = get_session_script_path_for(&directory, session.profile.as_deref());
+        if file_path.exists() {
+            eprintln!(
+                "Error: A session is already saved for {}, not moving `{}`",
+                &directory, &session.name
+            );
+            continue;
+        }
+
+        let shell_script = match fs::read_to_string(&session.path) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("Error: Could not read {}: {}", session.path.display(), err);
+                continue;
+            }
+        };
+
+        let prefix = if dry_run { "Would move" } else { "Moving" };
+        println!(
+            "{} session `{}`: {} -> {}",
+            prefix, &session.name, &session.directory, &directory
+        );
+        let shell_script = relocate_session_script(
+            &shell_script,
+            &session.directory,
+            &old_dir,
+            &new_dir,
+            |old, new| {
+                println!("  pane cwd {} -> {}", old, new);
+            },
+        );
+        let old_files_dir = get_session_files_dir(&session.path);
+        let new_files_dir = get_session_files_dir(&file_path);
+        let shell_script = relocate_editor_sessions(&shell_script, &old_files_dir, &new_files_dir);
+
+        if dry_run {
+            continue;
+        }
+
+        if let Err(err) = write_session_script(&file_path, &shell_script) {
+            eprintln!("Error: Could not write {}: {}", file_path.display(), err);
+            continue;
+        }
+        if let Err(err) = fs::remove_file(&session.path) {
+            eprintln!(
+                "Error: Could not remove {}: {}",
+                session.path.display(),
+                err
+            );
+            std::process::exit(1);
+        }
+        if old_files_dir.is_dir() {
+            if let Err(err) = fs::rename(&old_files_dir, &new_files_dir) {
+                eprintln!("Error: Could not move {}: {}", old_files_dir.display(), err);
+            }
+        }
+    }
+
+    if dry_run {
+        println!("Dry run, nothing was changed.");
+    }
+}
+
+/// Rewrites the cwd of every pane under `old_dir` to point under `new_dir`,
+/// calling `on_relocate` for each rewritten cwd. `root` is the directory the
+/// session was saved for, and is relocated the same way.
+pub fn relocate_session_script(
+    shell_script: &str,
+    root: &str,
+    old_dir: &str,
+    new_dir: &str,
+    mut on_relocate: impl FnMut(&str, &str),
+) -> String {
+    let new_root = relocate_path(root, old_dir, new_dir).unwrap_or(root.to_string());
+
+    TMUX_PANE_CWD_RE
+        .replace_all(shell_script, |caps: &regex::Captures| {
+            let line = caps.get(0).unwrap();
+            let word = caps.get(1).unwrap();
+            let cwd = expand_cwd(word.as_str(), root);
+
+            let new_cwd = relocate_path(&cwd, old_dir, new_dir).unwrap_or(cwd.clone());
+            let new_word = template_cwd(&new_cwd, &new_root);
+            // Cwds relative to the session's directory don't need to change
+            if new_word == word.as_str() {
+                return line.as_str().to_string();
+            }
+
+            on_relocate(&cwd, &new_cwd);
+            let command = &line.as_str()[..word.start() - line.start()];
+            format!("{}{}", command, new_word)
+        })
+        .to_string()
+}
+
+/// Returns `path` moved under `new_dir` if it is `old_dir` or one of its subdirectories.
+pub fn relocate_path(path: &str, old_dir: &str, new_dir: &str) -> Option<String> {
+    let rest = path.strip_prefix(old_dir)?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+    Some(format!("{}{}", new_dir, rest))
+}
+
+pub fn get_absolute_dir(dir: &str) -> String {
+    let path = env::current_dir().unwrap().join(dir);
+    // The old directory is usually gone already, so resolve `..` and `.`
+    // without touching the filesystem
+    let mut absolute_path = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                absolute_path.pop();
+            }
+            Component::CurDir => {}
+            component => absolute_path.push(component),
+        }
+    }
+    absolute_path.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relocates_paths_under_the_old_dir() {
+        assert_eq!(relocate_path("/a/b", "/a/b", "/c"), Some("/c".to_string()));
+        assert_eq!(
+            relocate_path("/a/b/d/e", "/a/b", "/c"),
+            Some("/c/d/e".to_string())
+        );
+        assert_eq!(relocate_path("/x/b", "/a/b", "/c"), None);
+        assert_eq!(relocate_path("/a", "/a/b", "/c"), None);
+        // Siblings sharing the prefix aren't under the old dir
+        assert_eq!(relocate_path("/a/bc", "/a/b", "/c"), None);
+        assert_eq!(relocate_path("/a/bc/d", "/a/b", "/c"), None);
+    }
+
+    #[test]
+    fn relocates_the_cwds_of_the_panes() {
+        let shell_script = "\
+pane_0_0=$(tmux new-window -t \"$session_name\" -n editor -c \"$PROJECT_ROOT\" -P -F '#{pane_id}' \"bash\")
+pane_0_1=$(tmux split-window -t \"$pane_0_0\" -c /srv/lib/src -P -F '#{pane_id}' \"bash\")
+pane_0_2=$(tmux split-window -t \"$pane_0_1\" -c /srv/library -P -F '#{pane_id}' \"bash\")
+";
+        let mut relocated = vec![];
+        let shell_script = relocate_session_script(
+            shell_script,
+            "/srv/web",
+            "/srv/lib",
+            "/opt/lib",
+            |old, new| relocated.push((old.to_string(), new.to_string())),
+        );
+
+        assert_eq!(
+            relocated,
+            vec![("/srv/lib/src".to_string(), "/opt/lib/src".to_string())]
+        );
+        assert!(shell_script.contains("-n editor -c \"$PROJECT_ROOT\" -P"));
+        assert!(shell_script.contains("-t \"$pane_0_0\" -c /opt/lib/src -P"));
+        assert!(shell_script.contains("-t \"$pane_0_1\" -c /srv/library -P"));
+    }
+
+    #[test]
+    fn keeps_the_cwds_relative_to_a_moved_root() {
+        let shell_script = "\
+pane_0_0=$(tmux new-window -t \"$session_name\" -n editor -c \"$PROJECT_ROOT\"/api -P -F '#{pane_id}' \"bash\")
+";
+        let mut relocated = vec![];
+        let new_script =
+            relocate_session_script(shell_script, "/srv/web", "/srv", "/opt", |old, new| {
+                relocated.push((old.to_string(), new.to_string()))
+            });
+
+        assert!(relocated.is_empty());
+        assert_eq!(new_script, shell_script);
+    }
+}
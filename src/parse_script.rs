@@ -0,0 +1,707 @@
+use crate::common::{
+    capture_session_name_from_script, expand_cwd, unquote_shell_word, TmuxPane, TmuxSession,
+    TmuxWindow,
+};
+use crate::hooks::parse_hooks;
+use crate::remote_pane::parse_ssh_command;
+use crate::shell_history::parse_history_variable;
+use crate::startup::parse_startup_annotations;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref PANE_ASSIGNMENT_RE: Regex = Regex::new(r"^(\w+)=\$\((.*)\)$").unwrap();
+    static ref SOCKET_RE: Regex =
+        Regex::new(r"(?m)^TMUXESSION_SOCKET_(NAME|PATH)=\$\{TMUXESSION_SOCKET_\w+-(.*)\}$")
+            .unwrap();
+}
+
+/// Reads a session script back into the session it was generated from. Only
+/// the tmux commands written by `generate_tmux_session_script` are understood,
+/// anything else added to the script is ignored.
+pub fn parse_session_script(shell_script: &str, root: &str) -> TmuxSession {
+    let mut session = TmuxSession {
+        name: capture_session_name_from_script(shell_script),
+        root: root.to_string(),
+        environment: vec![],
+        windows: vec![],
+        startup: parse_startup_annotations(shell_script).unwrap_or_default(),
+        hooks: parse_hooks(shell_script).unwrap_or_default(),
+    };
+
+    let mut pane_id = None;
+    let mut is_history = false;
+    for line in shell_script.lines() {
+        // The index of a split pane is only given in the comment before it
+        if let Some(id) = line.strip_prefix("# Create pane ") {
+            pane_id = Some(id.trim().to_string());
+        }
+        // The last command of the history typed in idle panes isn't one of
+        // their commands
+        if line.starts_with("# Type the last command of pane ") {
+            is_history = true;
+            continue;
+        }
+
+        // Windows and panes are created as `pane_<window>_<pane>=$(tmux ...)`,
+        // older scripts target them by index instead
+        let (created_pane, line) = match PANE_ASSIGNMENT_RE.captures(line.trim()) {
+            Some(caps) => (parse_pane_variable(&caps[1]), caps.get(2).unwrap().as_str()),
+            None => (None, line.trim()),
+        };
+
+        let mut words = split_shell_words(line);
+        // Commands of panes started by `tmuxession restore` are guarded by
+        // `deferred <pane> || ...`
+        if words.len() > 3 && words[0] == "deferred" && words[2] == "||" {
+            words.drain(..3);
+        }
+        if words.len() < 2 || words[0] != "tmux" {
+            continue;
+        }
+        let command = TmuxCommand::parse(&words[2..]);
+        let is_history = std::mem::take(&mut is_history);
+
+        match words[1].as_str() {
+            "new-window" => {
+                let (window_id, pane_id) = match created_pane {
+                    Some((window_id, pane_id)) => (window_id, pane_id),
+                    None => match command.get_target() {
+                        Some((window_id, _)) => (window_id, String::new()),
+                        None => continue,
+                    },
+                };
+                session.windows.push(TmuxWindow {
+                    id: window_id,
+                    name: command
+                        .get_option("-n")
+                        .map_or(String::new(), unquote_shell_word),
+                    layout: String::new(),
+                    active: false,
+                    zoomed: false,
+                    panes: vec![create_pane(pane_id, &command, root)],
+                });
+            }
+            "split-window" => {
+                let Some(window) = find_window(&mut session, &command) else {
+                    continue;
+                };
+                let pane_id = match created_pane {
+                    Some((_, pane_id)) => pane_id,
+                    None => pane_id.take().unwrap_or(window.panes.len().to_string()),
+                };
+                window.panes.push(create_pane(pane_id, &command, root));
+            }
+            "send-keys" => {
+                let Some(pane) = find_pane(&mut session, &command) else {
+                    continue;
+                };
+                match command.arguments.as_slice() {
+                    [keys, enter] if enter == "C-m" => {
+                        pane.commands.push(unquote_shell_word(keys));
+                    }
+                    [keys] if command.has_flag("-l") && !is_history => {
+                        pane.commands.push(unquote_shell_word(keys));
+                        pane.prefill = true;
+                    }
+                    _ => {}
+                }
+            }
+            "select-pane" => {
+                let Some(window) = find_window(&mut session, &command) else {
+                    continue;
+                };
+                for pane in &mut window.panes {
+                    pane.active = false;
+                }
+                if let Some(pane) = find_pane(&mut session, &command) {
+                    pane.active = true;
+                }
+            }
+            "select-window" => {
+                let window_id = command.get_target().map(|(window_id, _)| window_id);
+                for window in &mut session.windows {
+                    window.active = Some(&window.id) == window_id.as_ref();
+                }
+            }
+            "select-layout" => {
+                let Some(window) = find_window(&mut session, &command) else {
+                    continue;
+                };
+                if let Some(layout) = command.arguments.first() {
+                    window.layout = unquote_shell_word(layout);
+                }
+            }
+            "resize-pane" if command.has_flag("-Z") => {
+                if let Some(window) = find_window(&mut session, &command) {
+                    window.zoomed = true;
+                }
+            }
+            "set-environment" => {
+                if let [name, value] = command.arguments.as_slice() {
+                    session
+                        .environment
+                        .push((unquote_shell_word(name), unquote_shell_word(value)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for window in &mut session.windows {
+        for pane in &mut window.panes {
+            pane.remote = pane
+                .commands
+                .last()
+                .and_then(|command| parse_ssh_command(command));
+        }
+        if !window.panes.iter().any(|pane| pane.active) {
+            window.panes[0].active = true;
+        }
+    }
+    if !session.windows.iter().any(|window| window.active) {
+        if let Some(window) = session.windows.first_mut() {
+            window.active = true;
+        }
+    }
+
+    session
+}
+
+/// The socket name and path of the server a script was saved for, as given
+/// to its tmux wrapper.
+pub fn parse_script_socket(shell_script: &str) -> (Option<String>, Option<String>) {
+    let mut socket = (None, None);
+    for caps in SOCKET_RE.captures_iter(shell_script) {
+        let value = Some(unquote_shell_word(&caps[2])).filter(|value| !value.is_empty());
+        match &caps[1] {
+            "NAME" => socket.0 = value,
+            _ => socket.1 = value,
+        }
+    }
+    socket
+}
+
+struct TmuxCommand {
+    /// Flags with their value, if they take one
+    flags: Vec<(String, Option<String>)>,
+    arguments: Vec<String>,
+}
+
+impl TmuxCommand {
+    fn parse(words: &[String]) -> TmuxCommand {
+        let mut flags = vec![];
+        let mut arguments = vec![];
+        let mut words = words.iter();
+        while let Some(word) = words.next() {
+            if word.len() == 2 && word.starts_with('-') {
+                let value = if "-t -n -c -F -e".contains(word.as_str()) {
+                    words.next().cloned()
+                } else {
+                    None
+                };
+                flags.push((word.clone(), value));
+            } else {
+                arguments.push(word.clone());
+            }
+        }
+        TmuxCommand { flags, arguments }
+    }
+
+    fn get_option(&self, flag: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .find(|(name, _)| name == flag)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|(name, _)| name == flag)
+    }
+
+    /// The window and pane of a `"$pane_<window>_<pane>"` or a
+    /// `"$session_name":<window>.<pane>` target.
+    fn get_target(&self) -> Option<(String, Option<String>)> {
+        let target = self.get_option("-t")?;
+        if let Some(variable) = target
+            .strip_prefix("\"$")
+            .and_then(|target| target.strip_suffix('"'))
+        {
+            let (window_id, pane_id) = parse_pane_variable(variable)?;
+            return Some((window_id, Some(pane_id)));
+        }
+
+        let target = target.strip_prefix("\"$session_name\":")?;
+        match target.split_once('.') {
+            Some((window_id, pane_id)) => Some((window_id.to_string(), Some(pane_id.to_string()))),
+            None => Some((target.to_string(), None)),
+        }
+    }
+}
+
+fn parse_pane_variable(variable: &str) -> Option<(String, String)> {
+    let (window_id, pane_id) = variable.strip_prefix("pane_")?.split_once('_')?;
+    Some((window_id.to_string(), pane_id.to_string()))
+}
+
+fn create_pane(id: String, command: &TmuxCommand, root: &str) -> TmuxPane {
+    TmuxPane {
+        id,
+        cwd: command
+            .get_option("-c")
+            .map_or(root.to_string(), |cwd| expand_cwd(cwd, root)),
+        active: false,
+        commands: command
+            .arguments
+            .last()
+            .map(|shell_command| unquote_shell_word(shell_command))
+            .into_iter()
+            .collect(),
+        remote: None,
+        history: command
+            .flags
+            .iter()
+            .filter(|(flag, _)| flag == "-e")
+            .filter_map(|(_, value)| parse_history_variable(value.as_deref()?))
+            .collect(),
+        prefill: false,
+    }
+}
+
+fn find_window<'a>(
+    session: &'a mut TmuxSession,
+    command: &TmuxCommand,
+) -> Option<&'a mut TmuxWindow> {
+    let (window_id, _) = command.get_target()?;
+    session
+        .windows
+        .iter_mut()
+        .find(|window| window.id == window_id)
+}
+
+/// The pane targeted by a command. The script only records the index of the
+/// panes it splits, so the first pane of a window takes the first targeted
+/// index that isn't one of theirs.
+fn find_pane<'a>(session: &'a mut TmuxSession, command: &TmuxCommand) -> Option<&'a mut TmuxPane> {
+    let (_, pane_id) = command.get_target()?;
+    let pane_id = pane_id?;
+    let window = find_window(session, command)?;
+
+    if window.panes[0].id.is_empty() && !window.panes[1..].iter().any(|pane| pane.id == pane_id) {
+        window.panes[0].id = pane_id.clone();
+    }
+    window.panes.iter_mut().find(|pane| pane.id == pane_id)
+}
+
+/// Splits a line into shell words, keeping their quotes.
+pub fn split_shell_words(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            (None, '#') if word.is_empty() => break,
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '\\') | (Some('"'), '\\') => {
+                word.push(c);
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{PaneStartup, RemotePane, SessionHook};
+    use crate::config::init_test_config;
+    use crate::generate_script::generate_tmux_session_script;
+    use crate::hooks::HookEvent;
+    use crate::test_fixtures::{pane, session, window};
+    use std::env;
+
+    fn round_trip(session: &TmuxSession) -> TmuxSession {
+        init_test_config();
+        let shell_script = generate_tmux_session_script(session);
+        parse_session_script(&shell_script, &session.root)
+    }
+
+    #[test]
+    fn round_trips_windows_and_panes() {
+        let mut session = session(vec![
+            window(
+                "0",
+                "editor",
+                false,
+                vec![pane("0", "/srv/web", true, &["bash"])],
+            ),
+            window(
+                "1",
+                "my servers",
+                true,
+                vec![
+                    pane("0", "/srv/web/api", false, &["bash", "npm run dev"]),
+                    pane("1", "/srv/web/db", true, &["zsh", "make db"]),
+                    pane("2", "/srv/web", false, &["fish"]),
+                ],
+            ),
+        ]);
+        session.windows[1].zoomed = true;
+        session.environment = vec![
+            ("AWS_PROFILE".to_string(), "dev".to_string()),
+            ("GREETING".to_string(), "it's \"here\"".to_string()),
+        ];
+
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn round_trips_commands_with_special_characters() {
+        let session = session(vec![window(
+            "0",
+            "shell",
+            true,
+            vec![pane(
+                "0",
+                "/srv/web",
+                true,
+                &[
+                    "bash",
+                    r#"echo "a  b" 'c d' $HOME `date` \$x"#,
+                    r"grep -E '\d+\\' file # not a comment",
+                    "cd ~/a\\ b && ls | wc -l",
+                ],
+            )],
+        )]);
+
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn round_trips_cwds() {
+        let home = env::var("HOME").unwrap();
+        let session = session(vec![window(
+            "0",
+            "dirs",
+            true,
+            vec![
+                pane("0", "/srv/web/with space", true, &["bash"]),
+                pane("1", &format!("{}/notes", home), false, &["bash"]),
+                pane("2", "/tmp/it's", false, &["bash"]),
+                pane("3", "/srv/website", false, &["bash"]),
+            ],
+        )]);
+
+        let shell_script = generate_tmux_session_script(&session);
+        assert!(shell_script.contains(r#"-c "$PROJECT_ROOT""/with space""#));
+        assert!(shell_script.contains("-c ~/notes"));
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn targets_panes_by_id_whatever_their_index() {
+        let session = session(vec![
+            window(
+                "1",
+                "editor",
+                true,
+                vec![pane("1", "/srv/web", true, &["bash"])],
+            ),
+            window(
+                "3",
+                "servers",
+                false,
+                vec![
+                    pane("1", "/srv/web", false, &["bash", "npm start"]),
+                    pane("2", "/srv/web", true, &["bash"]),
+                    pane("4", "/srv/web", false, &["bash", "npm test"]),
+                ],
+            ),
+        ]);
+
+        init_test_config();
+        let shell_script = generate_tmux_session_script(&session);
+        assert!(shell_script.contains(
+            "pane_1_1=$(tmux split-window -t \"$pane_1_0\" -c \"$PROJECT_ROOT\" -P -F '#{pane_id}' \"bash\")"
+        ));
+        assert!(shell_script.contains(
+            "pane_1_2=$(tmux split-window -t \"$pane_1_1\" -c \"$PROJECT_ROOT\" -P -F '#{pane_id}' \"bash\")"
+        ));
+        assert!(shell_script.contains("tmux select-layout -t \"$pane_1_0\" "));
+        assert!(shell_script.contains("tmux kill-window -t \"$placeholder_window\"\n"));
+        assert!(!shell_script.contains("\"$session_name\":1"));
+
+        // Windows and panes are numbered by their position once restored
+        let parsed = parse_session_script(&shell_script, &session.root);
+        let ids: Vec<(&str, Vec<&str>)> = parsed
+            .windows
+            .iter()
+            .map(|window| {
+                let panes = window.panes.iter().map(|pane| pane.id.as_str()).collect();
+                (window.id.as_str(), panes)
+            })
+            .collect();
+        assert_eq!(ids, [("0", vec!["0"]), ("1", vec!["0", "1", "2"])]);
+        assert!(parsed.windows[0].active);
+        assert!(parsed.windows[1].panes[1].active);
+        assert_eq!(parsed.windows[1].panes[0].commands, ["bash", "npm start"]);
+        assert_eq!(parsed.windows[1].panes[2].commands, ["bash", "npm test"]);
+    }
+
+    #[test]
+    fn parses_scripts_targeting_panes_by_index() {
+        let shell_script = r#"#!/bin/bash
+session_name=web
+tmux new-session -d -s "$session_name"
+
+## Window 1:editor
+tmux new-window -t "$session_name":1 -k -n editor -c "$PROJECT_ROOT" "bash"
+
+# Create pane 2
+tmux split-window -t "$session_name":1 -c "$PROJECT_ROOT"/api "bash"
+# Run command in pane 2
+tmux send-keys -t "$session_name":1.2 "npm run dev" C-m
+
+# Select the active pane in window editor
+tmux select-pane -t "$session_name":1.2
+
+# Set layout for window editor
+tmux select-layout -t "$session_name":1 "even-horizontal"
+tmux select-window -t "$session_name":1
+"#;
+
+        let parsed = parse_session_script(shell_script, "/srv/web");
+        assert_eq!(
+            parsed,
+            session(vec![TmuxWindow {
+                layout: "even-horizontal".to_string(),
+                ..window(
+                    "1",
+                    "editor",
+                    true,
+                    vec![
+                        pane("", "/srv/web", false, &["bash"]),
+                        pane("2", "/srv/web/api", true, &["bash", "npm run dev"]),
+                    ],
+                )
+            }])
+        );
+    }
+
+    #[test]
+    fn parses_unescaped_commands_of_older_scripts() {
+        // Commands used to be written between double quotes as they were,
+        // they are read the way the shell running the script reads them
+        let shell_script = r#"#!/bin/bash
+session_name=web
+tmux new-session -d -s "$session_name"
+
+## Window 1:shell
+tmux new-window -t "$session_name":1 -k -n shell -c "$PROJECT_ROOT" "bash"
+# Run command in pane 1
+tmux send-keys -t "$session_name":1.1 "grep -r a\.b\\c src" C-m
+# Run command in pane 1
+tmux send-keys -t "$session_name":1.1 "echo \"$HOME\" 'it'" C-m
+"#;
+
+        let parsed = parse_session_script(shell_script, "/srv/web");
+        assert_eq!(
+            parsed.windows[0].panes[0].commands,
+            ["bash", r"grep -r a\.b\c src", r#"echo "$HOME" 'it'"#]
+        );
+    }
+
+    #[test]
+    fn round_trips_remote_panes() {
+        let mut session = session(vec![window(
+            "0",
+            "remote",
+            true,
+            vec![pane(
+                "0",
+                "/srv/web",
+                true,
+                &["bash", "ssh -p 2222 me@example.com"],
+            )],
+        )]);
+        session.windows[0].panes[0].remote = Some(RemotePane {
+            host: "example.com".to_string(),
+            user: Some("me".to_string()),
+            options: vec!["-p".to_string(), "2222".to_string()],
+            cwd: Some("/var/www/my app".to_string()),
+            command: Some("tail -f \"log/$(date +%F).log\"".to_string()),
+        });
+
+        let parsed = round_trip(&session);
+        assert_eq!(
+            parsed.windows[0].panes[0].remote,
+            session.windows[0].panes[0].remote
+        );
+        assert_eq!(round_trip(&parsed), parsed);
+    }
+
+    #[test]
+    fn gives_the_project_root_to_panes_using_it() {
+        let session = session(vec![window(
+            "0",
+            "editor",
+            true,
+            vec![
+                pane(
+                    "0",
+                    "/srv/web",
+                    true,
+                    &[
+                        "bash",
+                        "vim -S '$PROJECT_ROOT/.tmuxession.sh.d/pane_0_0.vim'",
+                    ],
+                ),
+                pane("1", "/srv/web", false, &["bash"]),
+            ],
+        )]);
+
+        init_test_config();
+        let shell_script = generate_tmux_session_script(&session);
+        assert!(shell_script.contains(r#"-c "$PROJECT_ROOT" -e PROJECT_ROOT="$PROJECT_ROOT" -P"#));
+        assert_eq!(shell_script.matches("-e PROJECT_ROOT=").count(), 1);
+        assert!(
+            shell_script.contains(r#""vim -S '\$PROJECT_ROOT/.tmuxession.sh.d/pane_0_0.vim'" C-m"#)
+        );
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn round_trips_pane_history() {
+        let mut session = session(vec![window(
+            "0",
+            "shell",
+            true,
+            vec![
+                pane("0", "/srv/web", true, &["bash"]),
+                pane("1", "/srv/web", false, &["bash", "npm run dev"]),
+            ],
+        )]);
+        session.windows[0].panes[0].history = vec![
+            "ls -la".to_string(),
+            r#"git commit -m "it's done""#.to_string(),
+        ];
+        session.windows[0].panes[1].history = vec!["npm install".to_string()];
+
+        init_test_config();
+        let shell_script = generate_tmux_session_script(&session);
+        assert!(shell_script.contains(
+            r#"-e TMUXESSION_HISTORY_1='ls -la' -e TMUXESSION_HISTORY_2='git commit -m "it'\''s done"' "#
+        ));
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn round_trips_prefilled_panes() {
+        let mut session = session(vec![window(
+            "0",
+            "shell",
+            true,
+            vec![
+                pane("0", "/srv/web", true, &["bash", "rm -rf \"$TMPDIR\"/cache"]),
+                pane("1", "/srv/web", false, &["bash", "npm run dev"]),
+            ],
+        )]);
+        session.windows[0].panes[0].prefill = true;
+        session.windows[0].panes[0].history = vec!["ls".to_string()];
+
+        init_test_config();
+        let shell_script = generate_tmux_session_script(&session);
+        assert!(shell_script.contains(
+            "tmux set-option -p -t \"$pane_0_0\" @tmuxession_prefill \"rm -rf \\\"\\$TMPDIR\\\"/cache\"\n"
+        ));
+        assert!(shell_script
+            .contains("deferred shell.0 || tmux send-keys -t \"$pane_0_0\" -l \"rm -rf"));
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn round_trips_deferred_commands() {
+        let mut session = session(vec![window(
+            "0",
+            "servers",
+            true,
+            vec![
+                pane("0", "/srv/web", true, &["bash", "docker compose up db"]),
+                pane("1", "/srv/web/api", false, &["bash", "npm run dev"]),
+            ],
+        )]);
+        session.startup = vec![PaneStartup {
+            pane: "servers.1".to_string(),
+            depends_on: vec!["servers.0".to_string()],
+            wait_for_output: Some("Listening on".to_string()),
+            delay: Some(0.5),
+            ..Default::default()
+        }];
+
+        init_test_config();
+        let shell_script = generate_tmux_session_script(&session);
+        assert!(shell_script
+            .contains("deferred servers.1 || tmux send-keys -t \"$pane_0_1\" \"npm run dev\" C-m"));
+        assert!(shell_script
+            .contains("tmux set-option -p -t \"$pane_0_1\" @tmuxession_pane servers.1\n"));
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn round_trips_hooks() {
+        let mut session = session(vec![window(
+            "0",
+            "shell",
+            true,
+            vec![pane("0", "/srv/web", true, &["bash"])],
+        )]);
+        session.hooks = vec![
+            SessionHook {
+                event: HookEvent::RestoreBefore,
+                command: "docker compose up -d".to_string(),
+            },
+            SessionHook {
+                event: HookEvent::RestoreAfter,
+                command: "tmux display-message \"$TMUXESSION_SESSION is ready\"".to_string(),
+            },
+            SessionHook {
+                event: HookEvent::Save,
+                command: "git stash list".to_string(),
+            },
+        ];
+
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn relocates_cwds_to_the_given_root() {
+        let session = session(vec![window(
+            "0",
+            "api",
+            true,
+            vec![pane("0", "/srv/web/api", true, &["bash"])],
+        )]);
+
+        init_test_config();
+        let shell_script = generate_tmux_session_script(&session);
+        let parsed = parse_session_script(&shell_script, "/home/me/web");
+        assert_eq!(parsed.windows[0].panes[0].cwd, "/home/me/web/api");
+    }
+}
@@ -0,0 +1,250 @@
+use crate::common::{shell_quote, shell_word, unquote_shell_word, RemotePane};
+use crate::parse_script::split_shell_words;
+use crate::tmux_commands::tmux_command;
+use lazy_static::lazy_static;
+use regex::Regex;
+use urlencoding::decode;
+
+lazy_static! {
+    /// `cd <dir> && <command>`, the remote command of a restored pane
+    static ref REMOTE_CD_RE: Regex = Regex::new(r#"^cd ((?:"[^"]*"|'[^']*'|[^\s"'])+) && (.*)$"#).unwrap();
+    /// Titles like `user@host: ~/dir`, which most shells set by default
+    static ref TITLE_CWD_RE: Regex = Regex::new(r"^(?:[^@\s]+@)?([^:\s]+):\s*([~/].*)$").unwrap();
+}
+
+/// The remote command that only starts the user's shell on the host
+const REMOTE_SHELL: &str = "exec $SHELL";
+
+/// Options of ssh that take a value.
+const SSH_VALUE_OPTIONS: &str = "BbcDEeFIiJLlmOoPpQRSWw";
+
+/// The SSH connection of a pane whose last command is `ssh`, with the remote
+/// directory the pane title or an OSC 7 sequence of the remote shell gives.
+pub fn capture_remote_pane(pane_target: &str, commands: &[String]) -> Option<RemotePane> {
+    let mut remote = parse_ssh_command(commands.last()?)?;
+
+    let output = tmux_command()
+        .args([
+            "display-message",
+            "-p",
+            "-t",
+            pane_target,
+            "#{host}\t#{host_short}\t#{pane_path}\t#{pane_title}",
+        ])
+        .output()
+        .expect("Failed to execute tmux command");
+    let output = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = output.trim_end_matches('\n').splitn(4, '\t').collect();
+    let [host, host_short, path, title] = fields.as_slice() else {
+        return Some(remote);
+    };
+
+    // Local shells may set them too, which is told apart by the host
+    let is_remote = |name: &str| !name.is_empty() && name != *host && name != *host_short;
+    let osc_cwd = path
+        .strip_prefix("file://")
+        .and_then(|url| url.split_once('/'))
+        .filter(|(name, _)| is_remote(name))
+        .and_then(|(_, path)| decode(path).ok())
+        .map(|path| format!("/{}", path));
+    let title_cwd = TITLE_CWD_RE
+        .captures(title)
+        .filter(|caps| is_remote(&caps[1]))
+        .map(|caps| caps[2].trim_end().to_string());
+    if let Some(cwd) = osc_cwd.or(title_cwd) {
+        remote.cwd = Some(cwd);
+    }
+
+    Some(remote)
+}
+
+/// Reads the host, options and remote command of an `ssh` command line,
+/// either as `ps` shows it or as `format_ssh_command` writes it.
+pub fn parse_ssh_command(command: &str) -> Option<RemotePane> {
+    let words: Vec<String> = split_shell_words(command)
+        .iter()
+        .map(|word| unquote_shell_word(word))
+        .collect();
+    let (program, args) = words.split_first()?;
+    if program.rsplit('/').next() != Some("ssh") {
+        return None;
+    }
+
+    let mut user = None;
+    let mut options = vec![];
+    let mut args = args.iter();
+    let destination = loop {
+        let arg = args.next()?;
+        if !arg.starts_with('-') || arg.len() < 2 {
+            break arg;
+        }
+        // A terminal is always requested for the remote command
+        if arg.trim_start_matches('-').chars().all(|c| c == 't') {
+            continue;
+        }
+        let takes_value = arg.len() == 2 && SSH_VALUE_OPTIONS.contains(&arg[1..]);
+        if !takes_value {
+            options.push(arg.clone());
+        } else if arg == "-l" {
+            user = args.next().cloned();
+        } else {
+            options.push(arg.clone());
+            options.push(args.next()?.clone());
+        }
+    };
+
+    let mut host = destination.to_string();
+    if let Some(url) = destination.strip_prefix("ssh://") {
+        host = url.to_string();
+        if let Some((url, port)) = url.rsplit_once(':') {
+            options.extend(["-p".to_string(), port.to_string()]);
+            host = url.to_string();
+        }
+    }
+    if let Some((name, rest)) = host.clone().rsplit_once('@') {
+        user = Some(name.to_string());
+        host = rest.to_string();
+    }
+
+    let mut remote = RemotePane {
+        host,
+        user,
+        options,
+        cwd: None,
+        command: None,
+    };
+    let command = args.cloned().collect::<Vec<_>>().join(" ");
+    let command = match REMOTE_CD_RE.captures(&command) {
+        Some(caps) => {
+            remote.cwd = Some(unquote_shell_word(&caps[1]));
+            caps[2].to_string()
+        }
+        None => command,
+    };
+    if !command.is_empty() && command != REMOTE_SHELL {
+        remote.command = Some(command);
+    }
+
+    Some(remote)
+}
+
+/// The ssh command reconnecting to the host, in the remote directory and
+/// running the remote command, or the user's shell, in it.
+pub fn format_ssh_command(remote: &RemotePane) -> String {
+    let mut words = vec!["ssh".to_string()];
+    words.extend(remote.options.iter().map(|option| shell_word(option)));
+
+    let destination = match &remote.user {
+        Some(user) => format!("{}@{}", user, &remote.host),
+        None => remote.host.clone(),
+    };
+    if remote.cwd.is_none() && remote.command.is_none() {
+        words.push(shell_word(&destination));
+        return words.join(" ");
+    }
+
+    let mut command = remote.command.clone().unwrap_or(REMOTE_SHELL.to_string());
+    if let Some(cwd) = &remote.cwd {
+        command = format!("cd {} && {}", remote_dir_word(cwd), command);
+    }
+    words.extend([
+        "-t".to_string(),
+        shell_word(&destination),
+        shell_quote(&command),
+    ]);
+    words.join(" ")
+}
+
+/// Quotes a remote directory, leaving `~` for the remote shell to expand.
+fn remote_dir_word(cwd: &str) -> String {
+    match cwd.strip_prefix("~/") {
+        Some(rest) => format!("~/{}", shell_word(rest)),
+        None if cwd == "~" => cwd.to_string(),
+        None => shell_word(cwd),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(host: &str, user: Option<&str>, options: &[&str]) -> RemotePane {
+        RemotePane {
+            host: host.to_string(),
+            user: user.map(|user| user.to_string()),
+            options: options.iter().map(|option| option.to_string()).collect(),
+            cwd: None,
+            command: None,
+        }
+    }
+
+    #[test]
+    fn parses_ssh_commands() {
+        assert_eq!(
+            parse_ssh_command("ssh me@example.com"),
+            Some(remote("example.com", Some("me"), &[]))
+        );
+        assert_eq!(
+            parse_ssh_command("/usr/bin/ssh -p 2222 -A -i ~/.ssh/deploy example.com"),
+            Some(remote(
+                "example.com",
+                None,
+                &["-p", "2222", "-A", "-i", "~/.ssh/deploy"]
+            ))
+        );
+        assert_eq!(
+            parse_ssh_command("ssh -l me ssh://example.com:2222"),
+            Some(remote("example.com", Some("me"), &["-p", "2222"]))
+        );
+        assert_eq!(
+            parse_ssh_command("ssh -t example.com htop -d 10"),
+            Some(RemotePane {
+                command: Some("htop -d 10".to_string()),
+                ..remote("example.com", None, &[])
+            })
+        );
+        assert_eq!(parse_ssh_command("sshd -D"), None);
+        assert_eq!(parse_ssh_command("ssh -p 2222"), None);
+    }
+
+    #[test]
+    fn formats_ssh_commands() {
+        let mut remote = remote("example.com", Some("me"), &["-p", "2222"]);
+        assert_eq!(format_ssh_command(&remote), "ssh -p 2222 me@example.com");
+
+        remote.cwd = Some("~/my app".to_string());
+        assert_eq!(
+            format_ssh_command(&remote),
+            r#"ssh -p 2222 -t me@example.com 'cd ~/"my app" && exec $SHELL'"#
+        );
+
+        remote.cwd = Some("/srv/it's".to_string());
+        remote.command = Some("tail -f log/*.log".to_string());
+        assert_eq!(
+            format_ssh_command(&remote),
+            r#"ssh -p 2222 -t me@example.com 'cd "/srv/it'\''s" && tail -f log/*.log'"#
+        );
+    }
+
+    #[test]
+    fn round_trips_ssh_commands() {
+        let remotes = [
+            remote("example.com", None, &[]),
+            RemotePane {
+                cwd: Some("~".to_string()),
+                ..remote("10.0.0.2", Some("deploy"), &["-i", "/keys/my key"])
+            },
+            RemotePane {
+                cwd: Some("/srv/with space/it's".to_string()),
+                command: Some("vim \"$HOME/notes\"".to_string()),
+                ..remote("example.com", Some("me"), &["-p", "2222"])
+            },
+        ];
+        for remote in remotes {
+            assert_eq!(
+                parse_ssh_command(&format_ssh_command(&remote)),
+                Some(remote)
+            );
+        }
+    }
+}
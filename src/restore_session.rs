@@ -1,20 +1,31 @@
 use crate::common::{
-    capture_session_name_from_script, get_session_script_path, is_inside_tmux, UserOption,
-    TMUX_SESSION_RE,
+    capture_session_name_from_script, find_session_script_path, get_current_dir,
+    get_script_profile, get_session_directory, is_inside_tmux, is_local_script, PaneStartup,
+    UserOption, TMUX_SESSION_RE,
 };
+use crate::config::{get_config, RestoreMode};
+use crate::generate_script::generate_tmux_wrapper;
+use crate::hooks::{parse_hooks, run_hooks, HookEvent};
+use crate::parse_script::{parse_script_socket, parse_session_script};
+use crate::session_file::verify_checksum;
+use crate::startup::{parse_startup_annotations, run_startup, PANE_KEY_OPTION};
+use crate::state::record_session_used;
+use crate::stop_session::record_session_origin;
 use crate::tmux_commands::{
-    attach_session, get_current_tmux_session, kill_session, switch_session,
+    attach_session, get_current_tmux_session, kill_session, set_script_socket, switch_session,
+    tmux_command,
 };
 use crate::ui::get_user_option;
+use std::ffi::OsString;
 use std::fs;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-pub fn restore_tmux_session(script: Option<String>) {
+pub fn restore_tmux_session(script: Option<String>, profile: Option<String>, force: bool) {
     let file_path = match script {
         Some(path) => PathBuf::from(path),
-        None => get_session_script_path(),
+        None => find_session_script_path(profile.as_deref()),
     };
 
     let mut shell_script = match fs::read_to_string(&file_path) {
@@ -25,6 +36,44 @@ pub fn restore_tmux_session(script: Option<String>) {
         }
     };
 
+    // Project-local scripts are edited and merged like the rest of the project
+    if !force && !is_local_script(&file_path) && !verify_checksum(&shell_script) {
+        eprintln!(
+            "Error: The checksum of {} doesn't match its content.",
+            file_path.display()
+        );
+        eprintln!("The script may have been truncated by an interrupted save or edited by hand.");
+        eprintln!("Review it with `tmuxession edit`, or restore it anyway with `--force`.");
+        std::process::exit(1);
+    }
+
+    let mut startup = parse_startup_annotations(&shell_script).unwrap_or_else(|err| {
+        eprintln!(
+            "Error: Invalid startup annotation in {}: {}",
+            file_path.display(),
+            err
+        );
+        std::process::exit(1);
+    });
+    let hooks = parse_hooks(&shell_script).unwrap_or_else(|err| {
+        eprintln!("Error: Invalid hook in {}: {}", file_path.display(), err);
+        std::process::exit(1);
+    });
+    if !startup.is_empty() && !shell_script.contains(PANE_KEY_OPTION) {
+        eprintln!("Warning: The script was saved by an older version of tmuxession and can't");
+        eprintln!("defer the commands of its panes, save the session again to use @startup.");
+        startup.clear();
+    }
+
+    // Without a socket given, the session is restored on the server it was
+    // saved for
+    let config = get_config();
+    let has_socket = config.socket_name.is_some() || config.socket_path.is_some();
+    if !has_socket {
+        let (socket_name, socket_path) = parse_script_socket(&shell_script);
+        set_script_socket(socket_name, socket_path);
+    }
+
     // Read the session_name value
     let mut session_name = capture_session_name_from_script(&shell_script);
     if session_name.is_empty() {
@@ -32,8 +81,15 @@ pub fn restore_tmux_session(script: Option<String>) {
         std::process::exit(1);
     }
 
+    let mut restore_mode = config.restore.mode;
     while check_session_exists(&session_name) {
-        let user_option = get_session_exists_option(&session_name);
+        let user_option = match restore_mode {
+            RestoreMode::Ask => get_session_exists_option(&session_name),
+            RestoreMode::Attach => 'A',
+            RestoreMode::Replace => 'K',
+        };
+        // Only the configured action is taken without asking, and only once
+        restore_mode = RestoreMode::Ask;
 
         match user_option {
             'A' => {
@@ -74,18 +130,69 @@ pub fn restore_tmux_session(script: Option<String>) {
         }
     }
 
+    // Pane cwds in the script are relative to the directory it was saved for,
+    // or the current one for scripts outside of the data directory
+    let project_root = get_session_directory(&file_path).unwrap_or_else(get_current_dir);
+    let hook_env = [
+        ("PROJECT_ROOT", project_root.as_str()),
+        ("TMUXESSION_SESSION", session_name.as_str()),
+    ];
+    if let Err(err) = run_hooks(&hooks, HookEvent::RestoreBefore, &project_root, &hook_env) {
+        eprintln!(
+            "Error: The on_restore_before hook {}, not restoring the session",
+            err
+        );
+        std::process::exit(1);
+    }
+
     println!("Restoring tmux session \"{}\"...", &session_name);
+    record_session_used(&file_path);
 
     // Run the script to restore the session detached
-    let mut script_execution = Command::new("sh")
+    let mut command = Command::new(&config.shell);
+    command
+        .env("PROJECT_ROOT", &project_root)
+        .env("TMUXESSION_DEFER_STARTUP", get_deferred_panes(&startup))
+        .env_remove("TMUXESSION_SOCKET_NAME")
+        .env_remove("TMUXESSION_SOCKET_PATH");
+    if has_socket {
+        // Make sure the script runs tmux on the given server, even if it was
+        // saved without a socket or for another one
+        command.envs(get_socket_variables(
+            config.socket_name.as_deref(),
+            config.socket_path.as_deref(),
+        ));
+        shell_script = format!("{}\n{}", generate_tmux_wrapper(None, None), &shell_script);
+    }
+    let mut script_execution = command
         .arg("-c")
         .arg(&shell_script)
         .spawn()
-        .expect("Failed to run shell script");
+        .unwrap_or_else(|err| {
+            eprintln!(
+                "Error: Could not run the script with {}: {}",
+                &config.shell, err
+            );
+            std::process::exit(1);
+        });
 
     let _ = script_execution
         .wait()
         .expect("Failed to wait for session restore script to finish");
+    record_session_origin(
+        &session_name,
+        &project_root,
+        get_script_profile(&file_path).as_deref(),
+    );
+
+    if !startup.is_empty() {
+        let session = parse_session_script(&shell_script, &project_root);
+        run_startup(&session_name, &session);
+    }
+
+    if let Err(err) = run_hooks(&hooks, HookEvent::RestoreAfter, &project_root, &hook_env) {
+        eprintln!("Warning: The on_restore_after hook {}", err);
+    }
 
     if is_inside_tmux() {
         switch_session(&session_name);
@@ -95,9 +202,36 @@ pub fn restore_tmux_session(script: Option<String>) {
     }
 }
 
-fn check_session_exists(session_name: &str) -> bool {
-    let output = Command::new("tmux")
-        .args(&["list-sessions", "-F", "#{session_name}"])
+/// The variables making the tmux wrapper of a script use the given socket.
+/// The other one is set empty rather than unset, so that the script's own
+/// wrapper doesn't fall back to the socket it was saved for.
+fn get_socket_variables(
+    socket_name: Option<&str>,
+    socket_path: Option<&Path>,
+) -> [(&'static str, OsString); 2] {
+    [
+        (
+            "TMUXESSION_SOCKET_NAME",
+            socket_name.unwrap_or_default().into(),
+        ),
+        (
+            "TMUXESSION_SOCKET_PATH",
+            socket_path.map_or(OsString::new(), |path| path.into()),
+        ),
+    ]
+}
+
+fn get_deferred_panes(startup: &[PaneStartup]) -> String {
+    startup
+        .iter()
+        .map(|startup| startup.pane.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn check_session_exists(session_name: &str) -> bool {
+    let output = tmux_command()
+        .args(["list-sessions", "-F", "#{session_name}"])
         .output()
         .expect("Failed to execute tmux command");
 
@@ -105,7 +239,7 @@ fn check_session_exists(session_name: &str) -> bool {
         let stderr = std::str::from_utf8(&output.stderr).expect("Failed to read stderr");
         // If tmux server is not running, then the session does not exist
         // return false and continue execution
-        if stderr.contains("no server running") {
+        if stderr.contains("no server running") || stderr.contains("error connecting to") {
             return false;
         }
         // Else something that we don't know happened, exit just to be sure
@@ -126,20 +260,69 @@ fn get_session_exists_option(session_name: &str) -> char {
         UserOption {
             keybind: 'A',
             label: "[A]ttach or switch to existing session".to_string(),
+            header: false,
         },
         UserOption {
             keybind: 'K',
             label: "[K]ill existing session and replace".to_string(),
+            header: false,
         },
         UserOption {
             keybind: 'R',
             label: "[R]estore with a different session name".to_string(),
+            header: false,
         },
         UserOption {
             keybind: 'q',
             label: "[q]uit".to_string(),
+            header: false,
         },
     ];
 
     get_user_option(&title, options)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Runs `tmux ls` in a restored script saved for `/tmp/work.sock`, with a
+    /// fake tmux printing its arguments.
+    fn run_restored_script(socket_name: Option<&str>, socket_path: Option<&Path>) -> String {
+        let bin_dir = env::temp_dir().join(format!("tmuxession-test-{}", std::process::id()));
+        fs::create_dir_all(&bin_dir).unwrap();
+        let fake_tmux = bin_dir.join("tmux");
+        fs::write(&fake_tmux, "#!/bin/sh\necho \"$@\"\n").unwrap();
+        fs::set_permissions(&fake_tmux, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let shell_script = format!(
+            "{}\n{}\ntmux ls\n",
+            generate_tmux_wrapper(None, None),
+            generate_tmux_wrapper(None, Some("/tmp/work.sock"))
+        );
+        let path = format!("{}:{}", bin_dir.display(), env::var("PATH").unwrap());
+        let output = Command::new("sh")
+            .env("PATH", path)
+            .envs(get_socket_variables(socket_name, socket_path))
+            .arg("-c")
+            .arg(&shell_script)
+            .output()
+            .unwrap();
+        let _ = fs::remove_dir_all(&bin_dir);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[test]
+    fn given_socket_overrides_the_one_of_the_script() {
+        assert_eq!(
+            run_restored_script(Some("personal"), None),
+            "-L personal ls"
+        );
+        assert_eq!(
+            run_restored_script(None, Some(Path::new("/tmp/other.sock"))),
+            "-S /tmp/other.sock ls"
+        );
+    }
+}
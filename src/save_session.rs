@@ -1,37 +1,121 @@
-use crate::common::{get_session_script_path, TmuxPane, TmuxSession, TmuxWindow};
-use crate::generate_script::generate_tmux_session_script;
+use crate::common::{
+    capture_session_name_from_script, get_current_dir, get_default_shell,
+    get_local_script_path_for, get_session_files_dir, get_session_script_path_for,
+    is_shell_command, PaneStartup, SessionHook, TmuxPane, TmuxSession, TmuxWindow,
+};
+use crate::config::get_config;
+use crate::editor_session::save_editor_sessions;
+use crate::generate_script::{generate_tmux_session_script, PREFILL_OPTION};
+use crate::hooks::{parse_hooks, run_hooks, HookEvent};
+use crate::remote_pane::capture_remote_pane;
+use crate::session_file::write_session_script;
+use crate::shell_history::get_pane_history;
+use crate::startup::parse_startup_annotations;
+use crate::state::record_session_used;
+use crate::tmux_commands::tmux_command;
+use std::fs;
+use std::path::{self, Path, PathBuf};
 use std::process::Command;
-use std::{fs, path::PathBuf};
 
-pub fn save_tmux_session(script: Option<String>, provided_session_name: Option<String>) {
+pub fn save_tmux_session(
+    script: Option<String>,
+    provided_session_name: Option<String>,
+    local: bool,
+    profile: Option<String>,
+) {
     let session_name = match provided_session_name {
         Some(name) => name,
         None => get_tmux_session_name(),
     };
 
-    let windows = get_tmux_windows();
-    let session = TmuxSession {
-        name: session_name.clone(),
-        windows,
-    };
-
+    let root = get_current_dir();
     let file_path = match script {
         Some(path) => PathBuf::from(path),
-        None => get_session_script_path(),
+        // The project-local script is shared with the team, so it is only
+        // written when asked to
+        None if local => get_local_script_path_for(&root, profile.as_deref()),
+        None => get_session_script_path_for(&root, profile.as_deref()),
+    };
+
+    save_running_session(None, session_name, root, &file_path);
+}
+
+/// Captures a running session, the current one when `target` is `None`, and
+/// saves it to `file_path`.
+pub fn save_running_session(
+    target: Option<&str>,
+    session_name: String,
+    root: String,
+    file_path: &Path,
+) {
+    let (startup, hooks) = get_previous_annotations(file_path);
+    let windows = get_tmux_windows(target);
+    let mut session = TmuxSession {
+        name: session_name,
+        root,
+        environment: get_tmux_environment(target),
+        windows,
+        startup,
+        hooks,
     };
 
+    let hook_env = [
+        ("PROJECT_ROOT", session.root.as_str()),
+        ("TMUXESSION_SESSION", session.name.as_str()),
+    ];
+    if let Err(err) = run_hooks(&session.hooks, HookEvent::Save, &session.root, &hook_env) {
+        eprintln!("Error: The on_save hook {}, not saving the session", err);
+        std::process::exit(1);
+    }
+
+    // The editors are started with their sessions by absolute path, or
+    // relative to the project for the scripts inside it, so that the panes
+    // find them whatever directory they are restored in
+    let files_dir = path::absolute(get_session_files_dir(file_path)).unwrap();
+    save_editor_sessions(&mut session.windows, target, &files_dir, &session.root);
+
     let shell_script = generate_tmux_session_script(&session);
-    fs::write(&file_path, shell_script).unwrap();
+    save_session_script(file_path, &shell_script);
+}
+
+pub fn save_session_script(file_path: &Path, shell_script: &str) {
+    if let Err(err) = write_session_script(file_path, shell_script) {
+        eprintln!("Error: Could not save {}: {}", file_path.display(), err);
+        std::process::exit(1);
+    }
+    record_session_used(file_path);
 
-    println!("Tmux session `{}` saved successfully.", &session_name);
+    println!(
+        "Tmux session `{}` saved successfully.",
+        capture_session_name_from_script(shell_script)
+    );
     println!(
         "Script for restoring the session saved under: {}",
         &file_path.display()
     );
 }
 
+/// The startup and hook annotations of the script being overwritten, which
+/// are kept when the session is saved again.
+fn get_previous_annotations(file_path: &Path) -> (Vec<PaneStartup>, Vec<SessionHook>) {
+    let Ok(shell_script) = fs::read_to_string(file_path) else {
+        return (vec![], vec![]);
+    };
+    let annotations = parse_startup_annotations(&shell_script)
+        .and_then(|startup| Ok((startup, parse_hooks(&shell_script)?)));
+    annotations.unwrap_or_else(|err| {
+        eprintln!(
+            "Error: Invalid annotation in {}: {}",
+            file_path.display(),
+            err
+        );
+        eprintln!("Fix it with `tmuxession edit` before saving the session again.");
+        std::process::exit(1);
+    })
+}
+
 fn get_tmux_session_name() -> String {
-    let output = Command::new("tmux")
+    let output = tmux_command()
         .arg("display-message")
         .arg("-p")
         .arg("#S")
@@ -40,9 +124,35 @@ fn get_tmux_session_name() -> String {
     String::from_utf8_lossy(&output.stdout).trim().to_string()
 }
 
-fn get_tmux_windows() -> Vec<TmuxWindow> {
-    let output = Command::new("tmux")
-        .arg("list-windows")
+fn get_tmux_environment(target: Option<&str>) -> Vec<(String, String)> {
+    let capture_config = &get_config().capture;
+    if capture_config.environment.is_empty() {
+        return vec![];
+    }
+
+    let mut command = tmux_command();
+    command.arg("show-environment");
+    if let Some(target) = target {
+        command.arg("-t").arg(target);
+    }
+    let output = command.output().unwrap();
+
+    // Variables removed from the session environment are prefixed with `-`
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(name, _)| capture_config.is_environment_captured(name))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+fn get_tmux_windows(target: Option<&str>) -> Vec<TmuxWindow> {
+    let mut command = tmux_command();
+    command.arg("list-windows");
+    if let Some(target) = target {
+        command.arg("-t").arg(target);
+    }
+    let output = command
         .arg("-F")
         .arg("#{window_index}:#{window_name}:#{window_layout}:#{window_active}:#{window_zoomed_flag}")
         .output()
@@ -58,7 +168,11 @@ fn get_tmux_windows() -> Vec<TmuxWindow> {
             let layout = parts[2].to_string();
             let active = parts[3] == "1";
             let zoomed = parts[4] == "1";
-            let panes = get_tmux_panes(&id);
+            let target_window = match target {
+                Some(target) => format!("{}:{}", target, &id),
+                None => id.clone(),
+            };
+            let panes = get_tmux_panes(&target_window);
 
             TmuxWindow {
                 id,
@@ -73,7 +187,7 @@ fn get_tmux_windows() -> Vec<TmuxWindow> {
 }
 
 fn get_tmux_panes(window_id: &str) -> Vec<TmuxPane> {
-    let output = Command::new("tmux")
+    let output = tmux_command()
         .arg("list-panes")
         .arg("-t")
         .arg(window_id)
@@ -85,77 +199,218 @@ fn get_tmux_panes(window_id: &str) -> Vec<TmuxPane> {
 
     panes_output
         .lines()
-        .filter_map(|line| {
+        .map(|line| {
             let parts: Vec<&str> = line.split(':').collect();
             let pane_id = parts[0].to_string();
             let cwd = parts[1].to_string();
             let pid: i32 = parts[2].parse().unwrap();
             let active = parts[3] == "1";
-            let commands = get_full_command(pid);
+            let mut commands = filter_captured_commands(get_full_command(pid));
+            let pane_target = format!("{}.{}", window_id, &pane_id);
+
+            // A command typed on restore is still waiting in an idle shell
+            let mut prefill = false;
+            if let [shell] = commands.as_slice() {
+                if is_shell_command(shell) {
+                    if let Some(command) = get_prefilled_command(&pane_target) {
+                        commands.push(command);
+                        prefill = true;
+                    }
+                }
+            }
+
+            let remote = capture_remote_pane(&pane_target, &commands);
+            let history = get_pane_history(&pane_target);
 
-            Some(TmuxPane {
+            TmuxPane {
                 id: pane_id,
                 cwd,
                 active,
                 commands,
-            })
+                remote,
+                history,
+                prefill,
+            }
         })
         .collect()
 }
 
-fn sanitize_command_from_ps(stdout: Vec<u8>) -> String {
-    let command = String::from_utf8_lossy(&stdout).trim().to_string();
+/// The command the session script typed in the pane without running it.
+fn get_prefilled_command(pane_target: &str) -> Option<String> {
+    let output = tmux_command()
+        .args(["show-options", "-pqv", "-t", pane_target, PREFILL_OPTION])
+        .output()
+        .expect("Failed to execute tmux command");
+    let command = String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string();
+    Some(command).filter(|command| !command.is_empty())
+}
+
+fn filter_captured_commands(commands: Vec<String>) -> Vec<String> {
+    let capture_config = &get_config().capture;
+    let mut commands = commands.into_iter();
+
+    // The pane must run something, so fall back to the user's shell
+    let first_command = commands
+        .next()
+        .filter(|command| capture_config.is_command_captured(command))
+        .unwrap_or_else(get_default_shell);
+
+    std::iter::once(first_command)
+        .chain(commands.filter(|command| capture_config.is_command_captured(command)))
+        .collect()
+}
+
+fn sanitize_command_from_ps(command: &str) -> String {
+    let command = command.trim();
 
-    if command.starts_with("-") {
-        command[1..].to_string()
-    } else {
-        command
+    match command.strip_prefix('-') {
+        Some(stripped) => stripped.to_string(),
+        None => command.to_string(),
     }
 }
 
-fn get_full_command(pid: i32) -> Vec<String> {
-    let mut commands = vec![];
+struct Process {
+    pid: i32,
+    ppid: i32,
+    pgid: i32,
+    /// Process group in the foreground of its terminal
+    tpgid: i32,
+    args: String,
+}
 
-    // Use ps to get child processes of the given PID
+fn get_processes() -> Vec<Process> {
     let output = Command::new("ps")
-        .arg("--ppid")
-        .arg(pid.to_string())
-        .arg("-o")
-        .arg("pid=")
+        .args(["-e", "-o", "pid=,ppid=,pgid=,tpgid=,args="])
         .output()
         .unwrap();
-    let child_pids = String::from_utf8_lossy(&output.stdout)
+    String::from_utf8_lossy(&output.stdout)
         .lines()
-        .map(|line| line.trim().parse::<i32>().unwrap())
-        .collect::<Vec<_>>();
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pid = fields.next()?.parse().ok()?;
+            let ppid = fields.next()?.parse().ok()?;
+            let pgid = fields.next()?.parse().ok()?;
+            let tpgid = fields.next()?.parse().ok()?;
+            Some(Process {
+                pid,
+                ppid,
+                pgid,
+                tpgid,
+                args: fields.collect::<Vec<_>>().join(" "),
+            })
+        })
+        .collect()
+}
 
-    let output = Command::new("ps")
-        .arg("-p")
-        .arg(pid.to_string())
-        .arg("-o")
-        .arg("args=")
-        .output()
-        .unwrap();
-    let command = sanitize_command_from_ps(output.stdout);
-    commands.push(command);
-
-    // Iterate over child PIDs to find the actual command running in the pane
-    for child_pid in child_pids {
-        let output = Command::new("ps")
-            .arg("-p")
-            .arg(child_pid.to_string())
-            .arg("-o")
-            .arg("args=")
-            .output()
-            .unwrap();
-        let command = sanitize_command_from_ps(output.stdout);
+fn get_full_command(pid: i32) -> Vec<String> {
+    get_pane_commands(pid, &get_processes())
+}
 
+/// The command of the pane's process, and the command of the job in the
+/// foreground of the pane when it is another one, like one run by its shell.
+fn get_pane_commands(pid: i32, processes: &[Process]) -> Vec<String> {
+    let capture_config = &get_config().capture;
+    let get_command = |process: &Process| {
+        capture_config.rewrite_command(&sanitize_command_from_ps(&process.args))
+    };
+
+    let Some(pane_process) = processes.iter().find(|process| process.pid == pid) else {
+        return vec![];
+    };
+    let mut commands = vec![get_command(pane_process)];
+
+    // The foreground job is a process group, whose processes started by the
+    // pane's process are the commands of the pipeline that was typed. Jobs
+    // started further down only have their leader.
+    let tpgid = pane_process.tpgid;
+    if tpgid > 0 && tpgid != pane_process.pgid {
+        let mut job: Vec<&Process> = processes
+            .iter()
+            .filter(|process| process.pgid == tpgid && process.ppid == pid)
+            .collect();
+        if job.is_empty() {
+            job = processes
+                .iter()
+                .filter(|process| process.pid == tpgid)
+                .collect();
+        }
+        let command = job
+            .iter()
+            .map(|process| get_command(process))
+            .collect::<Vec<_>>()
+            .join(" | ");
         // Ignore empty commands and tmuxession commands
         if !command.is_empty() && !command.contains("tmuxession") {
             commands.push(command);
-            break;
         }
     }
 
     commands
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_config;
+
+    fn process(pid: i32, ppid: i32, pgid: i32, tpgid: i32, args: &str) -> Process {
+        Process {
+            pid,
+            ppid,
+            pgid,
+            tpgid,
+            args: args.to_string(),
+        }
+    }
+
+    #[test]
+    fn keeps_the_shell_of_idle_panes() {
+        init_test_config();
+        let processes = [
+            process(1, 0, 1, -1, "tmux new-session"),
+            process(10, 1, 10, 10, "-bash"),
+        ];
+        assert_eq!(get_pane_commands(10, &processes), ["bash"]);
+        assert!(get_pane_commands(20, &processes).is_empty());
+    }
+
+    #[test]
+    fn joins_the_commands_of_pipelines() {
+        init_test_config();
+        let processes = [
+            process(10, 1, 10, 11, "bash"),
+            process(11, 10, 11, 11, "tail -f app.log"),
+            process(12, 10, 11, 11, "grep error"),
+            // Started by the commands of the pipeline, not typed
+            process(13, 11, 11, 11, "sh -c inotifywait"),
+        ];
+        assert_eq!(
+            get_pane_commands(10, &processes),
+            ["bash", "tail -f app.log | grep error"]
+        );
+    }
+
+    #[test]
+    fn keeps_the_leader_of_nested_jobs() {
+        init_test_config();
+        let processes = [
+            process(10, 1, 10, 12, "bash"),
+            process(11, 10, 11, 12, "bash"),
+            process(12, 11, 12, 12, "htop"),
+            process(13, 12, 12, 12, "htop-helper"),
+        ];
+        assert_eq!(get_pane_commands(10, &processes), ["bash", "htop"]);
+    }
+
+    #[test]
+    fn ignores_tmuxession_commands() {
+        init_test_config();
+        let processes = [
+            process(10, 1, 10, 11, "bash"),
+            process(11, 10, 11, 11, "tmuxession save"),
+        ];
+        assert_eq!(get_pane_commands(10, &processes), ["bash"]);
+    }
+}
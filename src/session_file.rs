@@ -0,0 +1,84 @@
+use crate::common::get_data_dir;
+use lazy_static::lazy_static;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+lazy_static! {
+    static ref CHECKSUM_RE: Regex = Regex::new(r"(?m)^# checksum: ([0-9a-f]{64})\n").unwrap();
+}
+
+/// Writes a session script, stamping it with the checksum of its content.
+pub fn write_session_script(path: &Path, shell_script: &str) -> io::Result<()> {
+    write_file_atomically(path, &add_checksum(shell_script))
+}
+
+/// Writes to a temporary file next to `path` and renames it over `path` once
+/// it has been synced, so that readers never see a partially written file.
+/// Concurrent writers are serialized by a lock on the data directory.
+pub fn write_file_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let lock_file = File::create(get_data_dir().join(".lock"))?;
+    lock_file.lock()?;
+
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(content.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+
+    // Make sure the rename itself survives a crash
+    if let Some(parent) = path.parent() {
+        File::open(parent)?.sync_all()?;
+    }
+
+    lock_file.unlock()
+}
+
+/// Checks the checksum in the header `write_session_script` stamps a script
+/// with. Scripts without one, like the ones edited by hand, are accepted as
+/// they are.
+pub fn verify_checksum(shell_script: &str) -> bool {
+    match CHECKSUM_RE.captures(shell_script) {
+        Some(caps) => {
+            let content = CHECKSUM_RE.replace(shell_script, "");
+            caps[1] == get_checksum(&content)
+        }
+        None => true,
+    }
+}
+
+pub fn has_checksum(shell_script: &str) -> bool {
+    CHECKSUM_RE.is_match(shell_script)
+}
+
+/// Removes the checksum from the header of a script.
+pub fn remove_checksum(shell_script: &str) -> String {
+    CHECKSUM_RE.replace(shell_script, "").to_string()
+}
+
+fn add_checksum(shell_script: &str) -> String {
+    let content = remove_checksum(shell_script);
+    let checksum_line = format!("# checksum: {}\n", get_checksum(&content));
+
+    // Keep the shebang as the first line
+    match content.split_once('\n') {
+        Some((first_line, rest)) if first_line.starts_with("#!") => {
+            format!("{}\n{}{}", first_line, checksum_line, rest)
+        }
+        _ => format!("{}{}", checksum_line, content),
+    }
+}
+
+fn get_checksum(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
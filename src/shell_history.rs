@@ -0,0 +1,169 @@
+use crate::common::{shell_quote, unquote_shell_word, Shell};
+use crate::tmux_commands::tmux_command;
+
+/// Pane option the shell hook records the last commands of the pane in
+const HISTORY_OPTION: &str = "@tmuxession_history";
+/// Prefix of the variables restored panes get their history in
+const HISTORY_VARIABLE: &str = "TMUXESSION_HISTORY_";
+/// Strips the numbers, and the `*` of edited entries, bash lists its history
+/// with
+const BASH_HISTORY_SED: &str = r"s/^ *[0-9]*\*\{0,1\} *//";
+
+/// Prints the hook recording the last `size` commands run in each pane, and
+/// putting the commands saved with the session back in the shell history.
+pub fn print_shell_hook(shell: Shell, size: usize) {
+    let hook = match shell {
+        Shell::Bash => BASH_HOOK,
+        Shell::Zsh => ZSH_HOOK,
+        Shell::Fish => FISH_HOOK,
+    };
+    print!(
+        "{}",
+        hook.replace("{option}", HISTORY_OPTION)
+            .replace("{variable}", HISTORY_VARIABLE)
+            .replace("{size}", &size.to_string())
+            .replace("{sed}", BASH_HISTORY_SED)
+    );
+}
+
+// The saved commands are put back in the history at the first prompt, once the
+// history file has been read
+const BASH_HOOK: &str = r#"# Records the last commands run in the tmux pane for `tmuxession save`
+if [ -n "${TMUX_PANE:-}" ]; then
+    __tmuxession_record() {
+        local variable
+        for variable in $(compgen -v {variable} | sort -t_ -k3 -n); do
+            history -s "${!variable}"
+            unset "$variable"
+        done
+        tmux set-option -p -t "$TMUX_PANE" {option} \
+            "$(HISTTIMEFORMAT= history {size} | sed '{sed}')" 2>/dev/null
+    }
+    PROMPT_COMMAND="__tmuxession_record${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
+"#;
+
+const ZSH_HOOK: &str = r#"# Records the last commands run in the tmux pane for `tmuxession save`
+if [[ -n "${TMUX_PANE:-}" ]]; then
+    __tmuxession_record() {
+        local variable
+        for variable in ${(on)${(M)${(k)parameters}:#{variable}*}}; do
+            print -s -r -- "${(P)variable}"
+            unset "$variable"
+        done
+        tmux set-option -p -t "$TMUX_PANE" {option} "$(fc -ln -{size} 2>/dev/null)" 2>/dev/null
+    }
+    autoload -Uz add-zsh-hook
+    add-zsh-hook precmd __tmuxession_record
+fi
+"#;
+
+const FISH_HOOK: &str = r#"# Records the last commands run in the tmux pane for `tmuxession save`
+if set -q TMUX_PANE
+    function __tmuxession_record --on-event fish_prompt
+        for variable in (set -n | string match '{variable}*' | sort -t_ -k3 -n)
+            history append -- $$variable 2>/dev/null
+            set -e $variable
+        end
+        set -l commands (history --max {size})
+        tmux set-option -p -t $TMUX_PANE {option} (string join \n -- $commands[-1..1]) 2>/dev/null
+    end
+end
+"#;
+
+/// The commands the shell hook recorded for a pane, oldest first.
+pub fn get_pane_history(pane_target: &str) -> Vec<String> {
+    let output = tmux_command()
+        .args(["show-options", "-pqv", "-t", pane_target, HISTORY_OPTION])
+        .output()
+        .expect("Failed to execute tmux command");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|command| !command.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The variables panes are created with for the shell hook to put their
+/// commands back in the history, one per command so that they are kept on a
+/// single line of the script. Commands spanning several lines are left out.
+pub fn format_history_variables(history: &[String]) -> Vec<String> {
+    history
+        .iter()
+        .filter(|command| !command.contains('\n'))
+        .enumerate()
+        .map(|(i, command)| format!("{}{}={}", HISTORY_VARIABLE, i + 1, shell_quote(command)))
+        .collect()
+}
+
+/// The command of a variable written by `format_history_variables`, `None`
+/// for other variables.
+pub fn parse_history_variable(word: &str) -> Option<String> {
+    let (name, command) = word.split_once('=')?;
+    let number = name.strip_prefix(HISTORY_VARIABLE)?;
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(unquote_shell_word(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn round_trips_history_variables() {
+        let history = [
+            "ls -la".to_string(),
+            r#"git commit -m "it's done""#.to_string(),
+            "echo $HOME \\$(date) `id`".to_string(),
+            "printf 'a\nb'".to_string(),
+        ];
+
+        let variables = format_history_variables(&history);
+        assert_eq!(variables[0], "TMUXESSION_HISTORY_1='ls -la'");
+        assert_eq!(
+            variables[1],
+            r#"TMUXESSION_HISTORY_2='git commit -m "it'\''s done"'"#
+        );
+        assert!(variables.iter().all(|variable| !variable.contains('\n')));
+        let parsed: Vec<String> = variables
+            .iter()
+            .filter_map(|variable| parse_history_variable(variable))
+            .collect();
+        assert_eq!(parsed, history[..3]);
+    }
+
+    #[test]
+    fn ignores_other_variables() {
+        assert_eq!(parse_history_variable("TMUXESSION_HISTORY_=ls"), None);
+        assert_eq!(parse_history_variable("TMUXESSION_HISTORY_1a=ls"), None);
+        assert_eq!(parse_history_variable("EDITOR=vim"), None);
+        assert_eq!(parse_history_variable("TMUXESSION_HISTORY_3"), None);
+    }
+
+    #[test]
+    fn strips_the_numbers_of_bash_history() {
+        let history = "  498  ls -la\n  499* git commit -m \"a  b\"\n 1000  7z x  archive.7z\n";
+        let mut sed = Command::new("sed")
+            .arg(BASH_HISTORY_SED)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        sed.stdin
+            .take()
+            .unwrap()
+            .write_all(history.as_bytes())
+            .unwrap();
+        let output = sed.wait_with_output().unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "ls -la\ngit commit -m \"a  b\"\n7z x  archive.7z\n"
+        );
+    }
+}
@@ -0,0 +1,386 @@
+use crate::common::{
+    is_shell_command, shell_word, unquote_shell_word, PaneStartup, TmuxPane, TmuxSession,
+};
+use crate::config::get_config;
+use crate::parse_script::split_shell_words;
+use crate::tmux_commands::tmux_command;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref STARTUP_RE: Regex = Regex::new(r"(?m)^# @startup (.*)$").unwrap();
+}
+
+/// Pane option the session script sets to the key of each pane, for the
+/// startup to find the panes whatever their position
+pub const PANE_KEY_OPTION: &str = "@tmuxession_pane";
+
+/// Name of a pane in the startup annotations, its window name and its
+/// position in the window.
+pub fn get_pane_key(window_name: &str, position: usize) -> String {
+    format!("{}.{}", window_name, position)
+}
+
+pub fn parse_startup_annotations(shell_script: &str) -> Result<Vec<PaneStartup>, String> {
+    STARTUP_RE
+        .captures_iter(shell_script)
+        .map(|caps| parse_pane_startup(&caps[1]))
+        .collect()
+}
+
+fn parse_pane_startup(annotation: &str) -> Result<PaneStartup, String> {
+    let words = split_shell_words(annotation);
+    let Some((pane, options)) = words.split_first() else {
+        return Err("missing the pane".to_string());
+    };
+
+    let mut startup = PaneStartup {
+        pane: unquote_shell_word(pane),
+        ..Default::default()
+    };
+    for option in options {
+        let option = unquote_shell_word(option);
+        let Some((key, value)) = option.split_once('=') else {
+            return Err(format!("expected key=value, found `{}`", option));
+        };
+        let value = value.to_string();
+        match key {
+            "depends_on" => {
+                startup.depends_on = value
+                    .split(',')
+                    .filter(|pane| !pane.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "wait_for_port" => startup.wait_for_port = Some(value),
+            "wait_for_file" => startup.wait_for_file = Some(value),
+            "wait_for_output" => {
+                if let Err(err) = Regex::new(&value) {
+                    return Err(format!("invalid regex `{}`: {}", value, err));
+                }
+                startup.wait_for_output = Some(value);
+            }
+            "delay" => match value.parse::<f64>() {
+                Ok(delay) if delay.is_finite() && delay >= 0.0 => startup.delay = Some(delay),
+                _ => return Err(format!("invalid delay `{}`", value)),
+            },
+            "timeout" => match value.parse() {
+                Ok(timeout) => startup.timeout = Some(timeout),
+                Err(_) => return Err(format!("invalid timeout `{}`", value)),
+            },
+            _ => return Err(format!("unknown option `{}`", key)),
+        }
+    }
+
+    Ok(startup)
+}
+
+pub fn format_startup_annotation(startup: &PaneStartup) -> String {
+    let mut words = vec![shell_word(&startup.pane)];
+    if !startup.depends_on.is_empty() {
+        words.push(format!(
+            "depends_on={}",
+            shell_word(&startup.depends_on.join(","))
+        ));
+    }
+    let options = [
+        ("wait_for_port", &startup.wait_for_port),
+        ("wait_for_file", &startup.wait_for_file),
+        ("wait_for_output", &startup.wait_for_output),
+    ];
+    for (key, value) in options {
+        if let Some(value) = value {
+            words.push(format!("{}={}", key, shell_word(value)));
+        }
+    }
+    if let Some(delay) = startup.delay {
+        words.push(format!("delay={}", delay));
+    }
+    if let Some(timeout) = startup.timeout {
+        words.push(format!("timeout={}", timeout));
+    }
+
+    format!("# @startup {}", words.join(" "))
+}
+
+/// Runs the commands of the panes with startup annotations, which the session
+/// script left out, once the panes they depend on are ready.
+pub fn run_startup(session_name: &str, session: &TmuxSession) {
+    let pane_ids = get_pane_ids(session_name);
+    let mut panes: HashMap<String, (&String, &TmuxPane)> = HashMap::new();
+    for window in &session.windows {
+        for (p, pane) in window.panes.iter().enumerate() {
+            let pane_key = get_pane_key(&window.name, p);
+            if let Some(pane_id) = pane_ids.get(&pane_key) {
+                panes.insert(pane_key, (pane_id, pane));
+            }
+        }
+    }
+
+    let order = get_startup_order(&session.startup);
+    let mut failed: Vec<&str> = vec![];
+    for (i, startup) in order.iter().enumerate() {
+        print!("[{}/{}] {}: ", i + 1, order.len(), &startup.pane);
+        io::stdout().flush().unwrap();
+
+        let Some((pane_id, pane)) = panes.get(&startup.pane) else {
+            println!("no such pane, skipped");
+            failed.push(&startup.pane);
+            continue;
+        };
+        let failed_dependency = startup
+            .depends_on
+            .iter()
+            .find(|dependency| failed.contains(&dependency.as_str()));
+        if let Some(dependency) = failed_dependency {
+            println!("skipped, {} didn't start", dependency);
+            failed.push(&startup.pane);
+            continue;
+        }
+
+        if let Some(delay) = startup
+            .delay
+            .and_then(|delay| Duration::try_from_secs_f64(delay).ok())
+        {
+            thread::sleep(delay);
+        }
+
+        // The script started the pane with a shell, unless the annotation was
+        // added by hand to a pane running another command
+        let commands = &pane.commands[1.min(pane.commands.len())..];
+        for (i, command) in commands.iter().enumerate() {
+            let args = if pane.prefill && i == commands.len() - 1 {
+                ["-l", command.as_str()]
+            } else {
+                [command.as_str(), "C-m"]
+            };
+            tmux_command()
+                .args(["send-keys", "-t", pane_id])
+                .args(args)
+                .output()
+                .expect("Failed to execute tmux command");
+        }
+        if !pane
+            .commands
+            .first()
+            .is_some_and(|command| is_shell_command(command))
+        {
+            print!("(already started) ");
+        }
+
+        match wait_until_ready(startup, pane_id, pane) {
+            Ok(None) => println!("started"),
+            Ok(Some(elapsed)) => println!("ready in {:.1}s", elapsed.as_secs_f64()),
+            Err(err) => {
+                println!("{}", err);
+                failed.push(&startup.pane);
+            }
+        }
+    }
+}
+
+/// Orders the panes after the panes they depend on, keeping the order of the
+/// annotations otherwise.
+fn get_startup_order(startup: &[PaneStartup]) -> Vec<&PaneStartup> {
+    let mut order: Vec<&PaneStartup> = Vec::new();
+    let mut remaining: Vec<&PaneStartup> = startup.iter().collect();
+
+    while !remaining.is_empty() {
+        // Dependencies without an annotation are started by the script
+        let next = remaining.iter().position(|pane| {
+            pane.depends_on
+                .iter()
+                .all(|dependency| !remaining.iter().any(|other| other.pane == *dependency))
+        });
+        match next {
+            Some(index) => order.push(remaining.remove(index)),
+            None => {
+                eprintln!(
+                    "Warning: The startup dependencies have a cycle, starting the rest in order"
+                );
+                order.append(&mut remaining);
+            }
+        }
+    }
+
+    order
+}
+
+/// The IDs of the panes of the session by the key the script gave them.
+fn get_pane_ids(session_name: &str) -> HashMap<String, String> {
+    let output = tmux_command()
+        .args(["list-panes", "-s", "-t", session_name, "-F"])
+        .arg(format!("#{{pane_id}} #{{{}}}", PANE_KEY_OPTION))
+        .output()
+        .expect("Failed to execute tmux command");
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(_, pane_key)| !pane_key.is_empty())
+        .map(|(pane_id, pane_key)| (pane_key.to_string(), pane_id.to_string()))
+        .collect()
+}
+
+/// Waits for the conditions of the annotation, returning how long it took or
+/// `None` if there is nothing to wait for.
+fn wait_until_ready(
+    startup: &PaneStartup,
+    pane_id: &str,
+    pane: &TmuxPane,
+) -> Result<Option<Duration>, String> {
+    let mut conditions = vec![];
+    if let Some(port) = &startup.wait_for_port {
+        conditions.push(format!("port {}", port));
+    }
+    if let Some(file) = &startup.wait_for_file {
+        conditions.push(format!("file {}", file));
+    }
+    if let Some(output) = &startup.wait_for_output {
+        conditions.push(format!("output /{}/", output));
+    }
+    if conditions.is_empty() {
+        return Ok(None);
+    }
+    print!("waiting for {}... ", conditions.join(", "));
+    io::stdout().flush().unwrap();
+
+    let output_re = startup
+        .wait_for_output
+        .as_ref()
+        .map(|output| Regex::new(output).unwrap());
+    let timeout = startup
+        .timeout
+        .unwrap_or(get_config().restore.startup_timeout);
+    let start = Instant::now();
+    loop {
+        let is_ready = startup
+            .wait_for_port
+            .as_ref()
+            .is_none_or(|port| is_port_open(port))
+            && startup
+                .wait_for_file
+                .as_ref()
+                .is_none_or(|file| get_file_path(file, &pane.cwd).exists())
+            && output_re
+                .as_ref()
+                .is_none_or(|output_re| output_re.is_match(&capture_output(pane_id, pane)));
+        if is_ready {
+            return Ok(Some(start.elapsed()));
+        }
+        if start.elapsed() >= Duration::from_secs(timeout) {
+            return Err(format!("timed out after {}s", timeout));
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+fn is_port_open(port: &str) -> bool {
+    let address = if port.contains(':') {
+        port.to_string()
+    } else {
+        format!("localhost:{}", port)
+    };
+    let Ok(addresses) = address.to_socket_addrs() else {
+        return false;
+    };
+    addresses
+        .into_iter()
+        .any(|address| TcpStream::connect_timeout(&address, Duration::from_millis(200)).is_ok())
+}
+
+/// Files are relative to the directory of the pane.
+fn get_file_path(file: &str, cwd: &str) -> std::path::PathBuf {
+    let file = match file.strip_prefix("~/") {
+        Some(rest) => format!("{}/{}", env::var("HOME").unwrap_or_default(), rest),
+        None => file.to_string(),
+    };
+    Path::new(cwd).join(file)
+}
+
+/// The content of the pane, without the commands typed in it which would
+/// match the output they wait for.
+fn capture_output(pane_id: &str, pane: &TmuxPane) -> String {
+    let output = tmux_command()
+        .args(["capture-pane", "-p", "-J", "-S", "-", "-t", pane_id])
+        .output()
+        .expect("Failed to execute tmux command");
+    let mut content = String::from_utf8_lossy(&output.stdout).to_string();
+    for command in &pane.commands {
+        content = content.replace(command.as_str(), "");
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_startup_annotations() {
+        let shell_script = "# @startup db.0 wait_for_port=5432\n\
+            # @startup 'my api.0' depends_on=db.0,cache.1 wait_for_output='Listening on \\d+' delay=1.5 timeout=120\n";
+
+        let startup = parse_startup_annotations(shell_script).unwrap();
+        assert_eq!(
+            startup,
+            [
+                PaneStartup {
+                    pane: "db.0".to_string(),
+                    wait_for_port: Some("5432".to_string()),
+                    ..Default::default()
+                },
+                PaneStartup {
+                    pane: "my api.0".to_string(),
+                    depends_on: vec!["db.0".to_string(), "cache.1".to_string()],
+                    wait_for_output: Some("Listening on \\d+".to_string()),
+                    delay: Some(1.5),
+                    timeout: Some(120),
+                    ..Default::default()
+                },
+            ]
+        );
+        for startup in &startup {
+            let annotation = format_startup_annotation(startup);
+            assert_eq!(
+                parse_startup_annotations(&annotation).unwrap(),
+                std::slice::from_ref(startup)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_delays() {
+        for delay in ["-1", "NaN", "inf", "-inf", "soon"] {
+            let annotation = format!("# @startup db.0 delay={}", delay);
+            assert_eq!(
+                parse_startup_annotations(&annotation),
+                Err(format!("invalid delay `{}`", delay))
+            );
+        }
+        let annotation = "# @startup db.0 delay=0";
+        assert_eq!(
+            parse_startup_annotations(annotation).unwrap()[0].delay,
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert_eq!(
+            parse_startup_annotations("# @startup db.0 wait=5"),
+            Err("unknown option `wait`".to_string())
+        );
+        assert_eq!(
+            parse_startup_annotations("# @startup db.0 5432"),
+            Err("expected key=value, found `5432`".to_string())
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use crate::common::get_data_dir;
+use crate::session_file::write_file_atomically;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Small state file in the data directory holding `<timestamp>\t<script path>`
+// lines, one for every session script that was saved or restored.
+const STATE_FILE: &str = "state";
+
+pub fn get_last_used() -> HashMap<PathBuf, u64> {
+    let state_path = get_data_dir().join(STATE_FILE);
+    let content = fs::read_to_string(state_path).unwrap_or_default();
+
+    content
+        .lines()
+        .filter_map(|line| {
+            let (timestamp, path) = line.split_once('\t')?;
+            let timestamp = timestamp.parse::<u64>().ok()?;
+            Some((PathBuf::from(path), timestamp))
+        })
+        .collect()
+}
+
+pub fn record_session_used(script_path: &Path) {
+    let script_path = fs::canonicalize(script_path).unwrap_or(script_path.to_path_buf());
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+
+    let mut last_used = get_last_used();
+    // Forget about scripts that have been removed in the meantime
+    last_used.retain(|path, _| path.exists());
+    last_used.insert(script_path, timestamp);
+
+    let mut content = String::new();
+    for (path, timestamp) in last_used {
+        content.push_str(&format!("{}\t{}\n", timestamp, path.display()));
+    }
+
+    let state_path = get_data_dir().join(STATE_FILE);
+    if let Err(err) = write_file_atomically(&state_path, &content) {
+        eprintln!(
+            "Warning: Could not update state file {}: {}",
+            state_path.display(),
+            err
+        );
+    }
+}
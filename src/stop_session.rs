@@ -0,0 +1,162 @@
+use crate::common::{get_session_script_path_for, is_inside_tmux};
+use crate::config::get_config;
+use crate::list_sessions::get_saved_sessions;
+use crate::restore_session::check_session_exists;
+use crate::save_session::save_running_session;
+use crate::tmux_commands::{get_current_tmux_session, kill_session, tmux_command};
+use std::collections::HashMap;
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Session options recording the directory and the profile of the script a
+/// session was restored from
+const ROOT_OPTION: &str = "@tmuxession_root";
+const PROFILE_OPTION: &str = "@tmuxession_profile";
+
+pub fn stop_session(session: Option<String>) {
+    let session_name = match session {
+        Some(name) => name,
+        None if is_inside_tmux() => get_current_tmux_session().unwrap_or_else(|| {
+            eprintln!("Error: Could not find the current tmux session");
+            std::process::exit(1);
+        }),
+        None => {
+            eprintln!("Error: Give the session to stop when running outside of tmux");
+            std::process::exit(1);
+        }
+    };
+    if !check_session_exists(&session_name) {
+        eprintln!(
+            "Error: No tmux session named `{}` is running",
+            &session_name
+        );
+        std::process::exit(1);
+    }
+
+    // Save the session for the directory and the profile it was restored
+    // from, like `tmuxession save --profile` would
+    let (root, profile) = match get_session_option(&session_name, ROOT_OPTION) {
+        Some(root) => (root, get_session_option(&session_name, PROFILE_OPTION)),
+        None => match get_saved_sessions()
+            .into_iter()
+            .find(|session| session.name == session_name)
+        {
+            Some(saved_session) => (saved_session.directory, saved_session.profile),
+            None => (get_session_path(&session_name), None),
+        },
+    };
+    let file_path = get_session_script_path_for(&root, profile.as_deref());
+    save_running_session(Some(&session_name), session_name.clone(), root, &file_path);
+
+    stop_panes(&session_name);
+
+    println!("Stopped tmux session `{}`.", &session_name);
+    if check_session_exists(&session_name) {
+        kill_session(&session_name);
+    }
+}
+
+/// Sends each pane the keys making its command exit, again whenever the pane
+/// moves on to another command, until all of them are closed or the timeout.
+fn stop_panes(session_name: &str) {
+    let stop_config = &get_config().stop;
+    // The pane running tmuxession is closed with the session
+    let own_pane = env::var("TMUX_PANE").unwrap_or_default();
+    let mut stopped_commands: HashMap<String, String> = HashMap::new();
+    let start = Instant::now();
+
+    loop {
+        let panes: Vec<(String, String)> = get_pane_commands(session_name)
+            .into_iter()
+            .filter(|(pane_id, _)| *pane_id != own_pane)
+            .collect();
+        if panes.is_empty() {
+            return;
+        }
+        if start.elapsed() >= Duration::from_secs(stop_config.timeout) {
+            println!(
+                "{} panes didn't exit after {}s, killing them.",
+                panes.len(),
+                stop_config.timeout
+            );
+            return;
+        }
+
+        for (pane_id, command) in panes {
+            if stopped_commands.get(&pane_id) == Some(&command) {
+                continue;
+            }
+            if let Some(keys) = stop_config.get_keys(&command) {
+                println!("Stopping `{}` in pane {}", &command, &pane_id);
+                tmux_command()
+                    .args(["send-keys", "-t", &pane_id])
+                    .args(keys)
+                    .output()
+                    .expect("Failed to execute tmux command");
+            }
+            stopped_commands.insert(pane_id, command);
+        }
+
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+fn get_pane_commands(session_name: &str) -> Vec<(String, String)> {
+    let output = tmux_command()
+        .args([
+            "list-panes",
+            "-s",
+            "-t",
+            session_name,
+            "-F",
+            "#{pane_id} #{pane_current_command}",
+        ])
+        .output()
+        .expect("Failed to execute tmux command");
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(pane_id, command)| (pane_id.to_string(), command.to_string()))
+        .collect()
+}
+
+/// Records where a restored session comes from, so that stopping it saves it
+/// there again.
+pub fn record_session_origin(session_name: &str, root: &str, profile: Option<&str>) {
+    let options = [(ROOT_OPTION, Some(root)), (PROFILE_OPTION, profile)];
+    for (option, value) in options {
+        if let Some(value) = value {
+            tmux_command()
+                .args(["set-option", "-t", session_name, option, value])
+                .output()
+                .expect("Failed to execute tmux command");
+        }
+    }
+}
+
+fn get_session_option(session_name: &str, option: &str) -> Option<String> {
+    let output = tmux_command()
+        .args(["show-options", "-qv", "-t", session_name, option])
+        .output()
+        .expect("Failed to execute tmux command");
+    let value = String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string();
+    Some(value).filter(|value| !value.is_empty())
+}
+
+fn get_session_path(session_name: &str) -> String {
+    let output = tmux_command()
+        .args([
+            "display-message",
+            "-p",
+            "-t",
+            session_name,
+            "#{session_path}",
+        ])
+        .output()
+        .expect("Failed to execute tmux command");
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
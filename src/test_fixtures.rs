@@ -0,0 +1,36 @@
+use crate::common::{TmuxPane, TmuxSession, TmuxWindow};
+
+pub fn pane(id: &str, cwd: &str, active: bool, commands: &[&str]) -> TmuxPane {
+    TmuxPane {
+        id: id.to_string(),
+        cwd: cwd.to_string(),
+        active,
+        commands: commands.iter().map(|command| command.to_string()).collect(),
+        remote: None,
+        history: vec![],
+        prefill: false,
+    }
+}
+
+pub fn window(id: &str, name: &str, active: bool, panes: Vec<TmuxPane>) -> TmuxWindow {
+    TmuxWindow {
+        id: id.to_string(),
+        name: name.to_string(),
+        layout: "5aed,176x79,0,0[176x59,0,0,0,176x19,0,60,1]".to_string(),
+        active,
+        zoomed: false,
+        panes,
+    }
+}
+
+/// A session saved for `/srv/web`.
+pub fn session(windows: Vec<TmuxWindow>) -> TmuxSession {
+    TmuxSession {
+        name: "web".to_string(),
+        root: "/srv/web".to_string(),
+        environment: vec![],
+        windows,
+        startup: vec![],
+        hooks: vec![],
+    }
+}
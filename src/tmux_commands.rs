@@ -1,8 +1,37 @@
+use crate::config::get_config;
 use std::process::Command;
+use std::sync::OnceLock;
+
+/// Socket name and path of the server the restored script was saved for
+static SCRIPT_SOCKET: OnceLock<(Option<String>, Option<String>)> = OnceLock::new();
+
+/// Creates a tmux command for the configured server socket, or the one of the
+/// restored script when none is configured.
+pub fn tmux_command() -> Command {
+    let config = get_config();
+    let (script_socket_name, script_socket_path) = SCRIPT_SOCKET.get().cloned().unwrap_or_default();
+    let mut command = Command::new("tmux");
+    if let Some(socket_path) = &config.socket_path {
+        command.arg("-S").arg(socket_path);
+    } else if let Some(socket_name) = &config.socket_name {
+        command.arg("-L").arg(socket_name);
+    } else if let Some(socket_path) = script_socket_path {
+        command.arg("-S").arg(socket_path);
+    } else if let Some(socket_name) = script_socket_name {
+        command.arg("-L").arg(socket_name);
+    }
+    command
+}
+
+/// Makes the tmux commands run on the server a script was saved for, unless
+/// another socket is configured.
+pub fn set_script_socket(socket_name: Option<String>, socket_path: Option<String>) {
+    let _ = SCRIPT_SOCKET.set((socket_name, socket_path));
+}
 
 pub fn attach_session(session_name: &str) {
     let args = vec!["attach", "-t", &session_name];
-    let mut tmux = Command::new("tmux")
+    let mut tmux = tmux_command()
         .args(&args)
         .spawn()
         .expect("Failed to spawn process");
@@ -13,7 +42,7 @@ pub fn attach_session(session_name: &str) {
 
 pub fn kill_session(session_name: &str) {
     let args = vec!["kill-session", "-t", &session_name];
-    let mut tmux = Command::new("tmux")
+    let mut tmux = tmux_command()
         .args(&args)
         .spawn()
         .expect("Failed to spawn process");
@@ -23,7 +52,7 @@ pub fn kill_session(session_name: &str) {
 
 pub fn switch_session(session_name: &str) {
     let args = vec!["switch-client", "-t", &session_name];
-    Command::new("tmux")
+    tmux_command()
         .args(&args)
         .spawn()
         .expect("Failed to spawn process");
@@ -32,7 +61,7 @@ pub fn switch_session(session_name: &str) {
 }
 
 pub fn get_current_pane_cwd() -> String {
-    let output = Command::new("tmux")
+    let output = tmux_command()
         .arg("display-message")
         .arg("-p")
         .arg("-F")
@@ -44,7 +73,7 @@ pub fn get_current_pane_cwd() -> String {
 }
 
 pub fn get_current_tmux_session() -> Option<String> {
-    let output = Command::new("tmux")
+    let output = tmux_command()
         .arg("display-message")
         .arg("-p")
         .arg("#S")
@@ -62,3 +91,32 @@ pub fn get_current_tmux_session() -> Option<String> {
         None
     }
 }
+
+pub fn display_menu(title: &str, items: &[(String, String, String)]) {
+    let mut args = vec![
+        "display-menu".to_string(),
+        "-T".to_string(),
+        title.to_string(),
+    ];
+    for (label, key, command) in items {
+        args.push(label.to_string());
+        args.push(key.to_string());
+        args.push(command.to_string());
+    }
+    let mut tmux = tmux_command()
+        .args(&args)
+        .spawn()
+        .expect("Failed to spawn process");
+
+    let _ = tmux.wait().expect("Failed to wait on tmux process");
+}
+
+pub fn display_popup(command: &str) {
+    let args = vec!["display-popup", "-E", command];
+    let mut tmux = tmux_command()
+        .args(&args)
+        .spawn()
+        .expect("Failed to spawn process");
+
+    let _ = tmux.wait().expect("Failed to wait on tmux process");
+}
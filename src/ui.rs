@@ -1,4 +1,5 @@
 use crate::common::UserOption;
+use crate::config::get_config;
 use crossterm::{
     cursor,
     event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
@@ -8,6 +9,15 @@ use crossterm::{
 };
 use std::io::{stdout, Write};
 
+fn get_colors() -> (Color, Color) {
+    // The colors are validated when loading the config
+    let ui_config = &get_config().ui;
+    (
+        Color::try_from(ui_config.selected_color.as_str()).unwrap(),
+        Color::try_from(ui_config.header_color.as_str()).unwrap(),
+    )
+}
+
 pub fn get_user_option(title: &str, options: Vec<UserOption>) -> char {
     let mut stdout = stdout();
     println!("{}\r", title);
@@ -17,21 +27,27 @@ pub fn get_user_option(title: &str, options: Vec<UserOption>) -> char {
     enable_raw_mode().unwrap();
     let char;
 
-    let mut selected_index = 0;
+    let (selected_color, header_color) = get_colors();
     let num_options = options.len();
+    let selectable: Vec<usize> = (0..num_options).filter(|&i| !options[i].header).collect();
+    // Index into the selectable options, headers are skipped when moving
+    let mut selected_index = 0;
+    let num_selectable = selectable.len();
 
     loop {
         // Move cursor to the beginning of the line and clear the line
         print!("\r");
         for (i, option) in options.iter().enumerate() {
-            if i == selected_index {
+            if option.header {
+                println!("{}\r", option.label.as_str().bold().with(header_color));
+            } else if i == selectable[selected_index] {
                 println!(
                     "{}{}\r",
-                    "> ".with(Color::Green),
-                    option.label.as_str().with(Color::Green)
+                    "> ".with(selected_color),
+                    option.label.as_str().with(selected_color)
                 );
             } else {
-                println!("{}{}\r", "  ", option.label);
+                println!("  {}\r", option.label);
             }
         }
 
@@ -45,16 +61,16 @@ pub fn get_user_option(title: &str, options: Vec<UserOption>) -> char {
                 KeyCode::Up | KeyCode::Char('k') if selected_index > 0 => {
                     selected_index -= 1;
                 }
-                KeyCode::Down | KeyCode::Char('j') if selected_index < num_options - 1 => {
+                KeyCode::Down | KeyCode::Char('j') if selected_index < num_selectable - 1 => {
                     selected_index += 1;
                 }
                 KeyCode::Enter => {
-                    char = options[selected_index].keybind;
+                    char = options[selectable[selected_index]].keybind;
                     break;
                 }
                 KeyCode::Char('n')
                     if modifiers.contains(KeyModifiers::CONTROL)
-                        && selected_index < num_options - 1 =>
+                        && selected_index < num_selectable - 1 =>
                 {
                     selected_index += 1;
                 }
@@ -68,7 +84,7 @@ pub fn get_user_option(title: &str, options: Vec<UserOption>) -> char {
                     break;
                 }
                 KeyCode::Char(c) => {
-                    if let Some(option) = options.iter().find(|o| o.keybind == c) {
+                    if let Some(option) = options.iter().find(|o| !o.header && o.keybind == c) {
                         char = option.keybind;
                         break;
                     }
@@ -90,6 +106,111 @@ pub fn get_user_option(title: &str, options: Vec<UserOption>) -> char {
     for _ in 0..(num_options + 1) {
         print!("\x1b[A\x1b[2K"); // Move cursor up and clear the line
     }
+    stdout.flush().unwrap();
 
     char
 }
+
+/// Lets the user check any of the labels, returning the indices of the
+/// checked ones.
+pub fn get_user_selection(title: &str, labels: Vec<String>) -> Vec<usize> {
+    let mut stdout = stdout();
+    println!("{}\r", title);
+    println!(
+        "{}\r",
+        "[space] toggle, [enter] confirm, [q] cancel".dark_grey()
+    );
+
+    execute!(stdout, cursor::Hide).unwrap();
+
+    enable_raw_mode().unwrap();
+
+    let (selected_color, _) = get_colors();
+    let mut selected_index = 0;
+    let num_options = labels.len();
+    // Everything is checked initially
+    let mut checked = vec![true; num_options];
+    let confirmed;
+
+    loop {
+        // Move cursor to the beginning of the line and clear the line
+        print!("\r");
+        for (i, label) in labels.iter().enumerate() {
+            let checkbox = if checked[i] { "[x] " } else { "[ ] " };
+            if i == selected_index {
+                println!(
+                    "{}{}{}\r",
+                    "> ".with(selected_color),
+                    checkbox.with(selected_color),
+                    label.as_str().with(selected_color)
+                );
+            } else {
+                println!("  {}{}\r", checkbox, label);
+            }
+        }
+
+        stdout.flush().unwrap();
+
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event::read().unwrap()
+        {
+            match code {
+                KeyCode::Up | KeyCode::Char('k') if selected_index > 0 => {
+                    selected_index -= 1;
+                }
+                KeyCode::Down | KeyCode::Char('j') if selected_index < num_options - 1 => {
+                    selected_index += 1;
+                }
+                KeyCode::Char('n')
+                    if modifiers.contains(KeyModifiers::CONTROL)
+                        && selected_index < num_options - 1 =>
+                {
+                    selected_index += 1;
+                }
+                KeyCode::Char('p')
+                    if modifiers.contains(KeyModifiers::CONTROL) && selected_index > 0 =>
+                {
+                    selected_index -= 1;
+                }
+                KeyCode::Char(' ') => {
+                    checked[selected_index] = !checked[selected_index];
+                }
+                KeyCode::Enter => {
+                    confirmed = true;
+                    break;
+                }
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    confirmed = false;
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        // Move cursor up to redraw the menu in place
+        for _ in 0..num_options {
+            print!("\x1b[A\x1b[2K"); // Move cursor up and clear the line
+        }
+    }
+
+    disable_raw_mode().unwrap();
+    execute!(stdout, cursor::Show).unwrap();
+
+    // Move cursor up to clear the checklist and its help line
+    for _ in 0..(num_options + 2) {
+        print!("\x1b[A\x1b[2K"); // Move cursor up and clear the line
+    }
+    stdout.flush().unwrap();
+
+    if !confirmed {
+        return vec![];
+    }
+
+    checked
+        .iter()
+        .enumerate()
+        .filter(|(_, checked)| **checked)
+        .map(|(i, _)| i)
+        .collect()
+}